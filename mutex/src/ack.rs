@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{BufferError, SafeRingBuffer};
+
+// Номер выдачи элемента в AckRingBuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeliveryId(u64);
+
+// Доставка "хотя бы один раз": извлеченный элемент остается "в полете",
+// пока его не подтвердят через ack. Неподтвержденные за ack_timeout
+// элементы возвращаются в голову буфера и выдаются повторно.
+#[derive(Debug)]
+pub struct AckRingBuffer {
+    buffer: SafeRingBuffer,
+    ack_timeout: Duration,
+    in_flight: Mutex<InFlight>,
+}
+
+#[derive(Debug, Default)]
+struct InFlight {
+    next_id: u64,
+    // Значение и срок подтверждения; None — срок не представим в Instant
+    // (огромный ack_timeout), элемент не истекает
+    entries: HashMap<DeliveryId, (u8, Option<Instant>)>,
+}
+
+impl AckRingBuffer {
+    pub fn new(capacity: usize, ack_timeout: Duration) -> Self {
+        AckRingBuffer {
+            buffer: SafeRingBuffer::new(capacity),
+            ack_timeout,
+            in_flight: Mutex::new(InFlight::default()),
+        }
+    }
+
+    pub fn push(&self, value: u8) -> Result<(), BufferError> {
+        self.buffer.push(value)
+    }
+
+    // Выдача головы с новым номером. Перед этим просроченные элементы
+    // возвращаются в голову в порядке первой выдачи; если места хватает не
+    // всем, возвращаются самые ранние, а остальные остаются в полете до
+    // следующего pop.
+    pub fn pop(&self) -> Option<(DeliveryId, u8)> {
+        let mut in_flight = self.in_flight.lock().expect("мьютекс доставок отравлен");
+        let now = Instant::now();
+        let mut expired: Vec<(DeliveryId, u8)> = in_flight
+            .entries
+            .iter()
+            .filter(|(_, (_, deadline))| deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(&id, &(value, _))| (id, value))
+            .collect();
+        expired.sort_by_key(|&(DeliveryId(id), _)| id);
+        let requeued = self.buffer.transaction(|ring| {
+            // Место под самые ранние выдачи; push_front ставит перед головой,
+            // поэтому кладем их от поздних к ранним
+            expired.truncate(ring.free());
+            for &(_, value) in expired.iter().rev() {
+                ring.push_front(value)
+                    .expect("место проверено под блокировкой");
+            }
+            expired
+        });
+        for (id, _) in requeued {
+            in_flight.entries.remove(&id);
+        }
+
+        let value = self.buffer.pop()?;
+        let id = DeliveryId(in_flight.next_id);
+        in_flight.next_id += 1;
+        in_flight
+            .entries
+            .insert(id, (value, now.checked_add(self.ack_timeout)));
+        Some((id, value))
+    }
+
+    // Подтверждение обработки; false — номер неизвестен (уже подтвержден
+    // или истек и был возвращен в буфер)
+    pub fn ack(&self, id: DeliveryId) -> bool {
+        let mut in_flight = self.in_flight.lock().expect("мьютекс доставок отравлен");
+        in_flight.entries.remove(&id).is_some()
+    }
+
+    // Подтверждение пакета под одной блокировкой; возвращает, сколько номеров
+    // действительно было в полете (неизвестные и повторы не считаются)
+    pub fn ack_many(&self, ids: &[DeliveryId]) -> usize {
+        let mut in_flight = self.in_flight.lock().expect("мьютекс доставок отравлен");
+        ids.iter()
+            .filter(|id| in_flight.entries.remove(id).is_some())
+            .count()
+    }
+
+    // Явный отказ: элемент сразу возвращается в голову буфера для повторной
+    // выдачи. Если буфер полон, элемент остается в полете уже просроченным
+    // и вернется при ближайшем pop, где найдется место. false — номер
+    // неизвестен.
+    pub fn nack(&self, id: DeliveryId) -> bool {
+        let mut in_flight = self.in_flight.lock().expect("мьютекс доставок отравлен");
+        let Some((value, deadline)) = in_flight.entries.get_mut(&id) else {
+            return false;
+        };
+        if self.buffer.push_front(*value).is_ok() {
+            in_flight.entries.remove(&id);
+        } else {
+            *deadline = Some(Instant::now());
+        }
+        true
+    }
+
+    // Сколько элементов выдано и не подтверждено
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .lock()
+            .expect("мьютекс доставок отравлен")
+            .entries
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_acked_is_not_redelivered() {
+        let buffer = AckRingBuffer::new(4, Duration::from_millis(10));
+        buffer.push(1).unwrap();
+        let (id, value) = buffer.pop().unwrap();
+        assert_eq!(value, 1);
+        assert!(buffer.ack(id));
+        assert!(!buffer.ack(id)); // Повторное подтверждение ничего не делает
+
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_unacked_is_redelivered_after_timeout() {
+        let buffer = AckRingBuffer::new(4, Duration::from_millis(20));
+        for value in [1, 2, 3] {
+            buffer.push(value).unwrap();
+        }
+        let (first, _) = buffer.pop().unwrap();
+        let (second, _) = buffer.pop().unwrap();
+        assert_eq!(buffer.in_flight(), 2);
+
+        // До истечения срока выдается следующий элемент
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(3));
+        thread::sleep(Duration::from_millis(30));
+
+        // Просроченные возвращаются в голову в исходном порядке, с новыми номерами
+        let (id, value) = buffer.pop().unwrap();
+        assert_eq!(value, 1);
+        assert_ne!(id, first);
+        assert!(!buffer.ack(first)); // Старый номер больше не действует
+        assert!(buffer.ack(id));
+        let (id, value) = buffer.pop().unwrap();
+        assert_eq!(value, 2);
+        assert_ne!(id, second);
+    }
+
+    #[test]
+    fn test_partial_requeue_keeps_earliest() {
+        let buffer = AckRingBuffer::new(3, Duration::from_millis(20));
+        for value in [1, 2, 3] {
+            buffer.push(value).unwrap();
+        }
+        for _ in 0..3 {
+            buffer.pop().unwrap();
+        }
+        buffer.push(4).unwrap();
+        buffer.push(5).unwrap();
+        thread::sleep(Duration::from_millis(30));
+
+        // Место только под один просроченный — возвращается самый ранний
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(1));
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(2));
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(3));
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(4));
+    }
+
+    #[test]
+    fn test_unbounded_ack_timeout() {
+        let buffer = AckRingBuffer::new(2, Duration::MAX);
+        buffer.push(1).unwrap();
+        let (id, _) = buffer.pop().unwrap(); // Без паники на переполнении Instant
+        assert_eq!(buffer.pop(), None); // Не истекает
+        assert!(buffer.nack(id)); // Явный отказ по-прежнему возвращает элемент
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(1));
+    }
+
+    #[test]
+    fn test_ack_many() {
+        let buffer = AckRingBuffer::new(4, Duration::from_millis(10));
+        for value in [1, 2, 3] {
+            buffer.push(value).unwrap();
+        }
+        let ids: Vec<_> = std::iter::from_fn(|| buffer.pop().map(|(id, _)| id)).collect();
+        assert_eq!(ids.len(), 3);
+
+        // Неизвестные и повторные номера не считаются
+        let unknown = DeliveryId(999);
+        assert_eq!(buffer.ack_many(&[ids[0], ids[1], unknown, ids[0]]), 2);
+        thread::sleep(Duration::from_millis(20));
+        // Вернулся только неподтвержденный
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(3));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_nack_requeues_at_front() {
+        let buffer = AckRingBuffer::new(4, Duration::from_secs(60));
+        for value in [1, 2] {
+            buffer.push(value).unwrap();
+        }
+        let (id, _) = buffer.pop().unwrap();
+        assert!(buffer.nack(id));
+        assert!(!buffer.nack(id)); // Уже не в полете
+        assert_eq!(buffer.in_flight(), 0);
+
+        // Без ожидания таймаута и перед остальными
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(1));
+        assert_eq!(buffer.pop().map(|(_, value)| value), Some(2));
+    }
+}
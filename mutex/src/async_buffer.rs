@@ -0,0 +1,115 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures::Stream;
+
+use crate::{BufferError, SafeRingBuffer};
+
+// Асинхронная обертка: записи и закрытие будят задачи, ждущие в потоке
+// (into_stream). Клон разделяет тот же буфер — так производитель
+// сохраняет ручку, когда потребитель забрал свою копию в поток.
+#[derive(Debug, Clone)]
+pub struct AsyncRingBuffer {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    buffer: SafeRingBuffer,
+    wakers: Mutex<Vec<Waker>>, // Ожидающие данных потоки
+}
+
+impl Shared {
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().expect("мьютекс waker отравлен"));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        AsyncRingBuffer {
+            shared: Arc::new(Shared {
+                buffer: SafeRingBuffer::new(capacity),
+                wakers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn push(&self, value: u8) -> Result<(), BufferError> {
+        self.shared.buffer.push(value)?;
+        self.shared.wake_all();
+        Ok(())
+    }
+
+    // Закрытие: поток дочитывает остаток и завершается
+    pub fn close(&self) {
+        self.shared.buffer.close();
+        self.shared.wake_all();
+    }
+
+    // Поток элементов по мере поступления; Ready(None) — буфер закрыт и пуст
+    pub fn into_stream(self) -> impl Stream<Item = u8> {
+        RingStream {
+            shared: self.shared,
+        }
+    }
+}
+
+struct RingStream {
+    shared: Arc<Shared>,
+}
+
+impl Stream for RingStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u8>> {
+        // Waker регистрируется до проверки буфера: запись, случившаяся
+        // между проверкой и возвратом Pending, все равно нас разбудит
+        {
+            let mut wakers = self.shared.wakers.lock().expect("мьютекс waker отравлен");
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        let buffer = &self.shared.buffer;
+        // Закрытие проверяется под той же блокировкой, что и извлечение
+        buffer.transaction(|ring| match ring.pop() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if buffer.is_closed() => Poll::Ready(None),
+            None => Poll::Pending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_stream_until_closed() {
+        let buffer = AsyncRingBuffer::new(2);
+        let producer = buffer.clone();
+        let stream = buffer.into_stream();
+
+        let producing = tokio::spawn(async move {
+            for value in 1..=5 {
+                // Вместимость 2 — ждем, пока поток разгребет место
+                while producer.push(value).is_err() {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+            producer.close();
+        });
+
+        let collected: Vec<u8> = stream.collect().await;
+        producing.await.unwrap();
+        assert_eq!(collected, vec![1, 2, 3, 4, 5]);
+    }
+}
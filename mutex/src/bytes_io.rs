@@ -0,0 +1,198 @@
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut};
+
+use crate::SafeRingBuffer;
+
+// Читающий адаптер: Buf поверх буфера, advance извлекает элементы.
+// chunk отдает снимок содержимого, снятый при создании (и заново, когда
+// снимок прочитан целиком), поэтому читатель у буфера должен быть один.
+#[derive(Debug)]
+pub struct RingBuf<'a> {
+    buffer: &'a SafeRingBuffer,
+    snapshot: Vec<u8>, // Копия содержимого от головы к хвосту
+    position: usize,   // Сколько байт снимка уже извлечено
+}
+
+impl<'a> RingBuf<'a> {
+    pub fn new(buffer: &'a SafeRingBuffer) -> Self {
+        RingBuf {
+            buffer,
+            snapshot: buffer.acquire().iter().collect(),
+            position: 0,
+        }
+    }
+}
+
+impl Buf for RingBuf<'_> {
+    fn remaining(&self) -> usize {
+        self.snapshot.len() - self.position
+    }
+
+    fn chunk(&self) -> &[u8] {
+        &self.snapshot[self.position..]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining(), "advance за пределы буфера");
+        let mut buffer = self.buffer.acquire();
+        for _ in 0..cnt {
+            buffer.pop();
+        }
+        self.position += cnt;
+        // Снимок исчерпан — подхватываем то, что успели дописать
+        if self.position == self.snapshot.len() {
+            self.snapshot = buffer.iter().collect();
+            self.position = 0;
+        }
+    }
+}
+
+// Пишущий адаптер: BufMut поверх буфера, advance_mut записывает элементы.
+// remaining_mut — число свободных слотов, так что put_* за пределы
+// вместимости паникует, как и для любого BufMut.
+// chunk_mut резервирует все свободные слоты (как begin_push), поэтому
+// другие производители не отнимут место между chunk_mut и advance_mut;
+// неиспользованный резерв отпускается при drop адаптера. Пока адаптер
+// держит резерв, остальные производители видят буфер заполненным.
+// Валидатор (with_validator) проверяется в advance_mut: BufMut не умеет
+// возвращать ошибку, поэтому отклоненный байт — паника, и тогда из этого
+// advance_mut в буфер не попадает ничего.
+#[derive(Debug)]
+pub struct RingBufMut<'a> {
+    buffer: &'a SafeRingBuffer,
+    scratch: Vec<u8>, // Сюда пишет вызывающий до advance_mut
+    reserved: usize,  // Слоты, зарезервированные в chunk_mut
+}
+
+impl<'a> RingBufMut<'a> {
+    pub fn new(buffer: &'a SafeRingBuffer) -> Self {
+        RingBufMut {
+            buffer,
+            scratch: Vec::new(),
+            reserved: 0,
+        }
+    }
+}
+
+// Safety: advance_mut переносит в буфер ровно cnt байт, записанных
+// вызывающим в начало chunk_mut; место под них зарезервировано, а
+// remaining_mut не растет сам по себе от вызовов адаптера.
+unsafe impl BufMut for RingBufMut<'_> {
+    fn remaining_mut(&self) -> usize {
+        self.reserved + self.buffer.acquire().free()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(cnt <= self.reserved, "advance_mut за пределы chunk_mut");
+        let values = &self.scratch[..cnt];
+        if self.buffer.validate_all(values).is_err() {
+            panic!("RingBufMut: значение отклонено валидатором");
+        }
+        let mut buffer = self.buffer.acquire();
+        buffer.reserved -= cnt;
+        self.reserved -= cnt;
+        buffer
+            .push_slice(values)
+            .expect("место под cnt байт зарезервировано");
+        drop(buffer);
+        self.buffer.observe_all(values);
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let mut buffer = self.buffer.acquire();
+        let free = buffer.free();
+        buffer.reserved += free;
+        drop(buffer);
+        self.reserved += free;
+        self.scratch.resize(self.reserved, 0);
+        UninitSlice::new(&mut self.scratch)
+    }
+}
+
+impl Drop for RingBufMut<'_> {
+    fn drop(&mut self) {
+        if self.reserved > 0 {
+            self.buffer.release_reserved(self.reserved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BufferError;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_buf_mut_then_buf() {
+        let buffer = SafeRingBuffer::new(4);
+        let mut writer = RingBufMut::new(&buffer);
+        assert_eq!(writer.remaining_mut(), 4);
+        writer.put_slice(&[1, 2, 3]);
+        assert_eq!(writer.remaining_mut(), 1);
+        assert_eq!(buffer.len(), 3);
+
+        let mut reader = RingBuf::new(&buffer);
+        assert_eq!(reader.remaining(), 3);
+        assert_eq!(reader.get_u8(), 1);
+        assert_eq!(buffer.len(), 2); // advance действительно извлекает
+
+        writer.put_u8(4);
+        writer.put_u8(5); // Место освободил читатель
+        assert_eq!(writer.remaining_mut(), 0);
+
+        // Дописанное видно после того, как прочитан прежний снимок
+        assert_eq!(reader.remaining(), 2);
+        let mut out = [0u8; 2];
+        reader.copy_to_slice(&mut out);
+        assert_eq!(out, [2, 3]);
+        assert_eq!(reader.remaining(), 2);
+        reader.copy_to_slice(&mut out);
+        assert_eq!(out, [4, 5]); // Порядок FIFO сохранен
+        assert_eq!(reader.remaining(), 0);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_buf_mut_holds_reservation() {
+        let buffer = SafeRingBuffer::new(4);
+        let mut writer = RingBufMut::new(&buffer);
+        let chunk = writer.chunk_mut();
+        assert_eq!(chunk.len(), 4);
+        chunk.write_byte(0, 1);
+
+        // Между chunk_mut и advance_mut другой производитель места не получит
+        assert_eq!(buffer.push(9), Err(BufferError::Full { capacity: 4 }));
+        unsafe { writer.advance_mut(1) };
+        assert_eq!(writer.remaining_mut(), 3);
+        drop(writer);
+
+        // Неиспользованный резерв отпущен вместе с адаптером
+        buffer.push(2).unwrap();
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_buf_mut_with_validator() {
+        let buffer = SafeRingBuffer::new(4).with_validator(|&value| value != 0);
+        let mut writer = RingBufMut::new(&buffer);
+        writer.put_slice(&[1, 2]);
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2]);
+
+        // Отклоненный байт — паника, и из того же put_slice не пишется ничего
+        let rejected = panic::catch_unwind(AssertUnwindSafe(|| {
+            writer.put_slice(&[3, 0]);
+        }));
+        assert!(rejected.is_err());
+        drop(writer);
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2]);
+        buffer.push_slice(&[3, 4]).unwrap(); // Резерв паниковавшего отпущен
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_buf_mut_respects_capacity() {
+        let buffer = SafeRingBuffer::new(2);
+        RingBufMut::new(&buffer).put_slice(&[1, 2, 3]);
+    }
+}
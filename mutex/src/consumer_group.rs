@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use crate::SafeRingBuffer;
+
+// Группа потребителей над общим буфером. Каждый потребитель (клон группы)
+// вызывает next и спит на условной переменной, пока нет данных, вместо
+// активного опроса. Извлечение идет под мьютексом буфера, поэтому каждый
+// элемент достается ровно одному потребителю.
+#[derive(Debug, Clone)]
+pub struct ConsumerGroup {
+    buffer: Arc<SafeRingBuffer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(buffer: Arc<SafeRingBuffer>) -> Self {
+        ConsumerGroup { buffer }
+    }
+
+    // Следующий элемент; блокируется, пока буфер пуст.
+    // None — буфер закрыт и полностью вычитан.
+    pub fn next(&self) -> Option<u8> {
+        self.buffer.pop_blocking().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::thread;
+
+    #[test]
+    fn test_consumer_group_no_duplicates_no_losses() {
+        let buffer = Arc::new(SafeRingBuffer::new(8));
+        let group = ConsumerGroup::new(Arc::clone(&buffer));
+
+        // Четыре потребителя делят поток данных
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let group = group.clone();
+                thread::spawn(move || {
+                    let mut taken = Vec::new();
+                    while let Some(value) = group.next() {
+                        taken.push(value);
+                    }
+                    taken
+                })
+            })
+            .collect();
+
+        // Писатель ждет свободного места, если потребители отстают
+        for value in 0..=255u8 {
+            while buffer.push(value).is_err() {
+                thread::yield_now();
+            }
+        }
+        buffer.close();
+
+        let mut all = Vec::new();
+        for consumer in consumers {
+            all.extend(consumer.join().unwrap());
+        }
+        let unique: HashSet<u8> = all.iter().copied().collect();
+        assert_eq!(all.len(), 256); // Нет дублей
+        assert_eq!(unique, (0..=255).collect()); // Нет потерь
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::BufferError;
+
+// Ограниченная очередь с задержкой: элемент можно извлечь только после
+// его момента готовности ready_at. Хранятся пары (ready_at, значение)
+// в порядке записи; ready_at None — задержка так велика, что момент не
+// представим в Instant, и элемент не станет готов никогда.
+#[derive(Debug)]
+pub struct DelayRingBuffer {
+    capacity: usize,
+    entries: Mutex<VecDeque<(Option<Instant>, u8)>>,
+}
+
+impl DelayRingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        DelayRingBuffer {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, VecDeque<(Option<Instant>, u8)>> {
+        self.entries.lock().expect("мьютекс очереди отравлен")
+    }
+
+    // Запись, которая станет доступна через delay
+    pub fn push(&self, value: u8, delay: Duration) -> Result<(), BufferError> {
+        let mut entries = self.entries();
+        if entries.len() >= self.capacity {
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
+        }
+        entries.push_back((Instant::now().checked_add(delay), value));
+        Ok(())
+    }
+
+    // Самый старый (по записи) из готовых элементов. Неготовые элементы не
+    // блокируют готовые за ними: короткая задержка может "обогнать" длинную.
+    pub fn pop(&self) -> Option<u8> {
+        let now = Instant::now();
+        let mut entries = self.entries();
+        let index = entries
+            .iter()
+            .position(|&(ready_at, _)| ready_at.is_some_and(|ready_at| ready_at <= now))?;
+        entries.remove(index).map(|(_, value)| value)
+    }
+
+    // Сколько ждать готовности головы: ноль, если она уже готова,
+    // Duration::MAX — если не дождаться, None для пустой очереди
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        let entries = self.entries();
+        let &(ready_at, _) = entries.front()?;
+        Some(ready_at.map_or(Duration::MAX, |ready_at| {
+            ready_at.saturating_duration_since(Instant::now())
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_not_ready_before_delay() {
+        let queue = DelayRingBuffer::new(4);
+        queue.push(1, Duration::from_millis(50)).unwrap();
+
+        assert_eq!(queue.pop(), None); // Элемент есть, но еще не готов
+        assert_eq!(queue.len(), 1);
+        let remaining = queue.next_ready_in().unwrap();
+        assert!(remaining > Duration::ZERO && remaining <= Duration::from_millis(50));
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(queue.next_ready_in(), Some(Duration::ZERO));
+        assert_eq!(queue.pop(), Some(1));
+        assert!(queue.is_empty());
+        assert_eq!(queue.next_ready_in(), None);
+    }
+
+    #[test]
+    fn test_ready_element_passes_pending_one() {
+        let queue = DelayRingBuffer::new(2);
+        queue.push(1, Duration::from_secs(60)).unwrap();
+        queue.push(2, Duration::ZERO).unwrap();
+        assert_eq!(
+            queue.push(3, Duration::ZERO),
+            Err(BufferError::Full { capacity: 2 })
+        );
+
+        assert_eq!(queue.pop(), Some(2)); // Готов, хотя голова еще ждет
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_unrepresentable_delay_never_ready() {
+        let queue = DelayRingBuffer::new(2);
+        queue.push(1, Duration::MAX).unwrap(); // Без паники на переполнении Instant
+        queue.push(2, Duration::ZERO).unwrap();
+        assert_eq!(queue.next_ready_in(), Some(Duration::MAX));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use crate::{BufferError, SafeRingBuffer};
+
+// Производитель кадрами: отдельные байты копятся локально и попадают
+// в буфер только целым кадром из frame_size байт — одной блокировкой,
+// по принципу "все или ничего", так что потребитель никогда не видит
+// половину кадра. Незафиксированный хвост при drop теряется: в конце
+// работы вызывайте flush.
+#[derive(Debug)]
+pub struct FrameProducer {
+    buffer: Arc<SafeRingBuffer>,
+    frame_size: usize,
+    pending: Vec<u8>, // Накопленная часть текущего кадра
+}
+
+impl FrameProducer {
+    // frame_size = 0 трактуется как 1
+    pub fn new(buffer: Arc<SafeRingBuffer>, frame_size: usize) -> Self {
+        let frame_size = frame_size.max(1);
+        FrameProducer {
+            buffer,
+            frame_size,
+            pending: Vec::with_capacity(frame_size),
+        }
+    }
+
+    // Добавление байта; на последнем байте кадра кадр фиксируется. Если
+    // в буфере нет места, Full, а кадр (уже с этим байтом) остается
+    // накопленным — повторить фиксацию можно через flush.
+    pub fn push(&mut self, value: u8) -> Result<(), BufferError> {
+        self.pending.push(value);
+        if self.pending.len() >= self.frame_size {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    // Принудительная фиксация накопленного, даже неполного кадра
+    pub fn flush(&mut self) -> Result<(), BufferError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.buffer.push_slice(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    // Сколько байт накоплено и еще не видно в буфере
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_commit_whole() {
+        let buffer = Arc::new(SafeRingBuffer::new(16));
+        let mut producer = FrameProducer::new(Arc::clone(&buffer), 4);
+
+        for value in [1, 2, 3] {
+            producer.push(value).unwrap();
+        }
+        assert!(buffer.is_empty()); // Неполный кадр не виден
+        assert_eq!(producer.pending(), 3);
+
+        producer.push(4).unwrap();
+        assert_eq!(buffer.len(), 4); // Весь кадр сразу
+        assert_eq!(producer.pending(), 0);
+
+        producer.push(5).unwrap();
+        producer.flush().unwrap(); // Неполный кадр по требованию
+        assert_eq!(buffer.drain_frames::<5>(), (vec![[1, 2, 3, 4, 5]], vec![]));
+    }
+
+    #[test]
+    fn test_frame_kept_when_full() {
+        let buffer = Arc::new(SafeRingBuffer::new(3));
+        buffer.push(0).unwrap();
+        let mut producer = FrameProducer::new(Arc::clone(&buffer), 3);
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert_eq!(producer.push(3), Err(BufferError::Full { capacity: 3 }));
+        assert_eq!(buffer.len(), 1); // Ни одного байта кадра
+        assert_eq!(producer.pending(), 3);
+
+        buffer.pop();
+        producer.flush().unwrap();
+        assert_eq!(buffer.drain_frames::<3>(), (vec![[1, 2, 3]], vec![]));
+    }
+}
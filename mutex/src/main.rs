@@ -1,32 +1,676 @@
-use std::sync::{Arc, Mutex};
+// Большая часть API используется только в тестах, а не в демонстрации main
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, LockResult, Mutex, MutexGuard, PoisonError, TryLockError};
 use std::thread;
+use std::time::{Duration, Instant};
+
+mod ack;
+#[cfg(feature = "async")]
+mod async_buffer;
+#[cfg(feature = "bytes")]
+mod bytes_io;
+mod consumer_group;
+mod delay;
+mod frame;
+mod priority;
+mod rate_limited;
+mod receiver;
+mod scheduler;
+mod semaphore;
+mod spill;
+
+// Трассировка операций буфера через log::trace! (только с feature "log";
+// без нее макрос разворачивается в пустоту). Трассируются поэлементные
+// операции RingBuffer: push, push_front, push_slice, insert_sorted,
+// push_overwrite, push_evict_n, pop, pop_back, а также clear. Массовые и
+// структурные (drain_all, retain, resize и т.п.) и push_unchecked на
+// горячем пути не трассируются.
+macro_rules! trace_op {
+    ($buffer:expr, $op:expr) => {
+        #[cfg(feature = "log")]
+        log::trace!(
+            target: "ring_buffer",
+            "{} size={} capacity={}",
+            $op,
+            $buffer.size,
+            $buffer.capacity
+        );
+    };
+}
+
+// Точка переключения для тестов: вызывает хук планировщика текущего потока
+// (см. set_yield_hook). Вне тестов разворачивается в пустоту.
+macro_rules! yield_point {
+    ($point:expr) => {
+        #[cfg(test)]
+        yield_hook::run($point);
+    };
+}
+
+// Тестовый хук, вызываемый в yield_point! внутри push/pop — между проверкой
+// размера и записью. Позволяет тесту в выбранный момент отдать управление
+// другому потоку или проверить инварианты. Хук свой у каждого потока,
+// чтобы параллельные тесты не мешали друг другу.
+#[cfg(test)]
+mod yield_hook {
+    use std::cell::RefCell;
+
+    type Hook = Box<dyn Fn(&'static str)>;
+
+    thread_local! {
+        static HOOK: RefCell<Option<Hook>> = const { RefCell::new(None) };
+    }
+
+    // Хук для текущего потока; None снимает его
+    pub(crate) fn set_yield_hook(hook: Option<Hook>) {
+        HOOK.with(|slot| *slot.borrow_mut() = hook);
+    }
+
+    pub(crate) fn run(point: &'static str) {
+        HOOK.with(|slot| {
+            if let Some(hook) = slot.borrow().as_ref() {
+                hook(point);
+            }
+        });
+    }
+}
+
+// Арифметика индексов по кольцу. Вынесена отдельно, чтобы можно было
+// подставлять разные реализации (модуль, битовая маска) без дублирования
+// самого буфера.
+trait IndexStrategy {
+    // Следующий индекс после index
+    fn advance(&self, index: usize) -> usize;
+    // Индекс, отстоящий от base на by шагов вперед (by < вместимости)
+    fn offset(&self, base: usize, by: usize) -> usize;
+}
+
+// Деление по модулю — подходит для любой вместимости. Арифметика не
+// переполняется при любой capacity до usize::MAX: индексы меньше capacity,
+// и base + by не вычисляется, если сумма может выйти за usize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ModuloStrategy {
+    capacity: usize,
+}
+
+impl IndexStrategy for ModuloStrategy {
+    fn advance(&self, index: usize) -> usize {
+        // index < capacity <= usize::MAX, так что index + 1 не переполняется
+        if index + 1 == self.capacity {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    fn offset(&self, base: usize, by: usize) -> usize {
+        let to_end = self.capacity - base; // Шагов до конца кольца
+        if by >= to_end { by - to_end } else { base + by }
+    }
+}
+
+// Битовая маска — только для вместимости-степени двойки (mask = capacity - 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MaskStrategy {
+    mask: usize,
+}
+
+impl IndexStrategy for MaskStrategy {
+    // Перенос за usize не влияет на младшие биты, поэтому wrapping_add
+    // безопасен для любой маски
+    fn advance(&self, index: usize) -> usize {
+        index.wrapping_add(1) & self.mask
+    }
+
+    fn offset(&self, base: usize, by: usize) -> usize {
+        base.wrapping_add(by) & self.mask
+    }
+}
+
+// Стратегия, выбранная для конкретного буфера
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Indexing {
+    Modulo(ModuloStrategy),
+    Mask(MaskStrategy),
+}
+
+impl Indexing {
+    fn modulo(capacity: usize) -> Self {
+        Indexing::Modulo(ModuloStrategy { capacity })
+    }
+}
+
+impl IndexStrategy for Indexing {
+    fn advance(&self, index: usize) -> usize {
+        match self {
+            Indexing::Modulo(s) => s.advance(index),
+            Indexing::Mask(s) => s.advance(index),
+        }
+    }
+
+    fn offset(&self, base: usize, by: usize) -> usize {
+        match self {
+            Indexing::Modulo(s) => s.offset(base, by),
+            Indexing::Mask(s) => s.offset(base, by),
+        }
+    }
+}
+
+// Скользящий счетчик событий: окно из buckets корзин по width каждая.
+// Корзина помечается номером интервала с момента origin; корзина со
+// старым номером считается пустой, так что устаревшие данные выпадают
+// без отдельной очистки.
+#[derive(Debug, Clone)]
+struct RateCounter {
+    origin: Instant,
+    width: Duration,
+    counts: Vec<u64>, // События в корзине
+    epochs: Vec<u64>, // Номер интервала, к которому относится корзина
+}
+
+// Длина окна push_rate_per_sec в секундах (корзина — одна секунда)
+const PUSH_RATE_WINDOW_SECS: usize = 10;
+
+impl RateCounter {
+    fn new(width: Duration, buckets: usize) -> Self {
+        RateCounter {
+            origin: Instant::now(),
+            width,
+            counts: vec![0; buckets],
+            epochs: vec![0; buckets],
+        }
+    }
+
+    // Номер интервала, в который попадает момент now
+    fn epoch(&self, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.origin).as_nanos() / self.width.as_nanos()) as u64
+    }
+
+    fn record(&mut self, now: Instant) {
+        let epoch = self.epoch(now);
+        let slot = (epoch % self.counts.len() as u64) as usize;
+        if self.epochs[slot] != epoch {
+            self.epochs[slot] = epoch;
+            self.counts[slot] = 0;
+        }
+        self.counts[slot] += 1;
+    }
+
+    // Среднее число событий в секунду по всему окну (включая текущую,
+    // еще не законченную корзину)
+    fn rate_per_sec(&self, now: Instant) -> f64 {
+        let current = self.epoch(now);
+        let buckets = self.counts.len() as u64;
+        let total: u64 = self
+            .epochs
+            .iter()
+            .zip(&self.counts)
+            .filter(|&(&epoch, _)| epoch <= current && current - epoch < buckets)
+            .map(|(_, &count)| count)
+            .sum();
+        total as f64 / (self.width.as_secs_f64() * buckets as f64)
+    }
+}
+
+// Массив слотов кольцевого буфера. Позволяет разместить хранилище где
+// угодно (например, в заранее выделенном пуле); длина не меняется.
+trait Storage {
+    fn get(&self, index: usize) -> &Option<u8>;
+    fn get_mut(&mut self, index: usize) -> &mut Option<u8>;
+    fn len(&self) -> usize;
+    // Все слоты подряд (для as_slices)
+    fn as_slice(&self) -> &[Option<u8>];
+}
+
+// Хранилище по умолчанию — обычный Vec
+#[derive(Debug, Clone)]
+struct VecStorage(Vec<Option<u8>>);
+
+impl Storage for VecStorage {
+    fn get(&self, index: usize) -> &Option<u8> {
+        &self.0[index]
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut Option<u8> {
+        &mut self.0[index]
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn as_slice(&self) -> &[Option<u8>] {
+        &self.0
+    }
+}
+
+// Хранилище поверх массива фиксированной длины (без кучи)
+#[derive(Debug, Clone)]
+struct ArrayStorage<const N: usize>([Option<u8>; N]);
+
+impl<const N: usize> Storage for ArrayStorage<N> {
+    fn get(&self, index: usize) -> &Option<u8> {
+        &self.0[index]
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut Option<u8> {
+        &mut self.0[index]
+    }
+
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn as_slice(&self) -> &[Option<u8>] {
+        &self.0
+    }
+}
 
 // Наш кольцевой буфер
-#[derive(Debug)]
-struct RingBuffer {
-    data: Vec<Option<u8>>, // Хранилище данных
-    head: usize,           // Указатель на начало (откуда читаем)
-    tail: usize,           // Указатель на конец (куда пишем)
-    size: usize,           // Текущее количество элементов
-    capacity: usize,       // Максимальная вместимость
+#[derive(Debug, Clone)]
+struct RingBuffer<S = VecStorage> {
+    data: S,            // Хранилище данных
+    head: usize,        // Указатель на начало (откуда читаем)
+    tail: usize,        // Указатель на конец (куда пишем)
+    size: usize,        // Текущее количество элементов
+    capacity: usize,    // Максимальная вместимость
+    pushed: u64,        // Всего записано элементов
+    popped: u64,        // Всего извлечено элементов
+    overwritten: u64,   // Всего затерто старых элементов
+    running_sum: u64,   // Сумма текущего содержимого (окна)
+    indexing: Indexing, // Способ продвижения индексов по кольцу
+    reserved: usize,    // Слоты, зарезервированные незавершенными begin_push
+    // Последние изменения вместимости: (когда, старая, новая)
+    capacity_history: VecDeque<(Instant, usize, usize)>,
+    push_rate: RateCounter, // Записи за последние секунды (см. push_rate_per_sec)
+    compact_below: Option<f64>, // Порог автоуплотнения (см. with_auto_compact)
+    seqs: Vec<u64>,         // Порядковые номера элементов по слотам
+    next_seq: u64,          // Номер для следующей записи
+    op_seq: u64,            // Счетчик изменяющих доступов (см. snapshot_versioned)
+    generation: u64,        // Поколение содержимого (см. clear_if_generation)
+    // Номера извлеченных и вытесненных элементов с прошлого освобождения
+    // блокировки (ведется только под with_producer_quota)
+    removed_seqs: Option<Vec<u64>>,
 }
 
-// Ошибки буфера
+// Сколько последних изменений вместимости хранит capacity_history
+const CAPACITY_HISTORY_LEN: usize = 64;
+
+// Ошибки буфера (состояние данных)
 #[derive(Debug, PartialEq)]
 enum BufferError {
-    Full, // Буфер переполнен
+    // Буфер переполнен; capacity — вместимость буфера, для диагностики.
+    // Учтите: PartialEq сравнивает и capacity, т.е. Full { capacity: 3 }
+    // не равно Full { capacity: 5 }.
+    Full { capacity: usize },
+    Empty,                                       // Буфер пуст
+    ZeroCapacity,                                // Буфер нулевой вместимости
+    AllocFailed,                                 // Не удалось выделить память
+    Unexpected { found: u8 },                    // В голове буфера не то значение, что ожидалось
+    Invalid,                                     // Значение отклонено валидатором (with_validator)
+    Mismatch { expected: usize, actual: usize }, // Размер не совпал с ожидаемым
+    QuotaExceeded { producer: u64 }, // Производитель исчерпал квоту (with_producer_quota)
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferError::Full { capacity } => {
+                write!(f, "ring buffer full (capacity {capacity})")
+            }
+            BufferError::Empty => write!(f, "ring buffer empty"),
+            BufferError::ZeroCapacity => write!(f, "ring buffer has zero capacity"),
+            BufferError::AllocFailed => write!(f, "ring buffer allocation failed"),
+            BufferError::Invalid => write!(f, "value rejected by ring buffer validator"),
+            BufferError::Mismatch { expected, actual } => {
+                write!(
+                    f,
+                    "ring buffer holds {actual} elements, expected {expected}"
+                )
+            }
+            BufferError::QuotaExceeded { producer } => {
+                write!(f, "producer {producer} exceeded its ring buffer quota")
+            }
+            BufferError::Unexpected { found } => {
+                write!(f, "unexpected value at ring buffer head: {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BufferError {}
+
+// Ошибки операций над потокобезопасным буфером: к ошибкам данных
+// добавляются сбои самой операции (отравленный мьютекс, таймаут, закрытие)
+#[derive(Debug, PartialEq)]
+enum OpError {
+    Buffer(BufferError), // Ошибка состояния данных
+    Poisoned,            // Мьютекс отравлен паникой другого потока
+    LockTimeout,         // Не дождались блокировки или данных
+    Closed,              // Буфер закрыт
+    Reentrant,           // Повторный захват из того же потока (debug, StrictMode::Error)
 }
 
+impl From<BufferError> for OpError {
+    fn from(error: BufferError) -> Self {
+        OpError::Buffer(error)
+    }
+}
+
+impl fmt::Display for OpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpError::Buffer(e) => e.fmt(f),
+            OpError::Poisoned => write!(f, "ring buffer mutex poisoned"),
+            OpError::LockTimeout => write!(f, "ring buffer operation timed out"),
+            OpError::Closed => write!(f, "ring buffer closed"),
+            OpError::Reentrant => write!(f, "reentrant lock on SafeRingBuffer"),
+        }
+    }
+}
+
+impl std::error::Error for OpError {}
+
+// Срез записан не целиком (push_slice_reporting): accepted первых значений
+// в буфере, остаток values[accepted..] можно отправить повторно
+#[derive(Debug, PartialEq)]
+struct PartialPush {
+    accepted: usize,
+}
+
+impl fmt::Display for PartialPush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ring buffer accepted only {} values", self.accepted)
+    }
+}
+
+impl std::error::Error for PartialPush {}
+
+// Конструкторы и перестройка вместимости — только для VecStorage,
+// остальное работает с любым Storage
 impl RingBuffer {
     // Создаем новый буфер заданного размера
     fn new(capacity: usize) -> Self {
+        RingBuffer::with_storage(VecStorage(vec![None; capacity])) // Заполняем None
+    }
+
+    // Буфер на capacity слотов, заполненный из iter; остаток вместимости
+    // свободен для записи. Лишний элемент — Full (итератор дальше не читается).
+    fn with_capacity_from_iter<I: IntoIterator<Item = u8>>(
+        capacity: usize,
+        iter: I,
+    ) -> Result<RingBuffer, BufferError> {
+        let mut buffer = Self::new(capacity);
+        for value in iter {
+            buffer.push(value)?;
+        }
+        Ok(buffer)
+    }
+
+    // Новый буфер той же вместимости с элементами f(x) в прежнем порядке.
+    // Приближение: задумывался map::<B> со сменой типа элемента, но буфер
+    // не обобщен и хранит только u8, поэтому и f — u8 -> u8. Оставлен, так
+    // как преобразование на месте полезно и без смены типа; когда буфер
+    // станет RingBuffer<T>, сигнатура расширится до FnMut(A) -> B.
+    fn map<F: FnMut(u8) -> u8>(mut self, mut f: F) -> RingBuffer {
+        let capacity = self.capacity;
+        let mapped = self.drain_all().into_iter().map(&mut f);
+        RingBuffer::with_capacity_from_iter(capacity, mapped)
+            .expect("элементов не больше вместимости")
+    }
+
+    // Конструктор без аварийного завершения при нехватке памяти:
+    // ошибка выделения возвращается как BufferError::AllocFailed
+    fn try_with_capacity(capacity: usize) -> Result<Self, BufferError> {
+        if capacity == 0 {
+            return Err(BufferError::ZeroCapacity);
+        }
+        let mut buffer = Self::new(0);
+        buffer.data = VecStorage(Self::try_alloc_slots(capacity, None)?);
+        buffer.seqs = Self::try_alloc_slots(capacity, 0)?;
+        buffer.capacity = capacity;
+        buffer.indexing = Indexing::modulo(capacity);
+        Ok(buffer)
+    }
+
+    // Выделение слотов через try_reserve_exact вместо паники/abort
+    fn try_alloc_slots<T: Clone>(capacity: usize, fill: T) -> Result<Vec<T>, BufferError> {
+        let mut data = Vec::new();
+        data.try_reserve_exact(capacity)
+            .map_err(|_| BufferError::AllocFailed)?;
+        data.resize(capacity, fill);
+        Ok(data)
+    }
+
+    // Буфер с вместимостью, округленной вверх до степени двойки (5 -> 8,
+    // 0 -> 1). Индексы продвигаются битовой маской вместо деления по модулю.
+    // Паникует, если requested больше usize::MAX / 2 + 1 (следующей степени
+    // двойки в usize нет) — для таких запросов есть try_new_pow2.
+    fn new_pow2(requested: usize) -> Self {
+        let capacity = requested
+            .checked_next_power_of_two()
+            .expect("new_pow2: степень двойки не помещается в usize");
+        let mut buffer = Self::new(capacity);
+        buffer.indexing = Indexing::Mask(MaskStrategy { mask: capacity - 1 });
+        buffer
+    }
+
+    // То же без паники: непредставимая степень двойки и нехватка памяти —
+    // BufferError::AllocFailed
+    fn try_new_pow2(requested: usize) -> Result<Self, BufferError> {
+        let capacity = requested
+            .checked_next_power_of_two()
+            .ok_or(BufferError::AllocFailed)?;
+        let mut buffer = Self::try_with_capacity(capacity)?;
+        buffer.indexing = Indexing::Mask(MaskStrategy { mask: capacity - 1 });
+        Ok(buffer)
+    }
+
+    // Перенос содержимого в новое хранилище заданной вместимости (не меньше size).
+    // Элементы укладываются с нулевого слота, сумма окна не меняется.
+    // При нехватке памяти буфер остается прежним.
+    fn reallocate(&mut self, new_capacity: usize) -> Result<(), BufferError> {
+        debug_assert!(new_capacity >= self.size);
+        let mut data = Self::try_alloc_slots(new_capacity, None)?;
+        let mut seqs = Self::try_alloc_slots(new_capacity, 0)?;
+        for (i, value) in self.iter().enumerate() {
+            data[i] = Some(value);
+            seqs[i] = self.seqs[self.physical(i)];
+        }
+        self.data = VecStorage(data);
+        self.seqs = seqs;
+        self.relayout(new_capacity);
+        Ok(())
+    }
+
+    // Уменьшение без перевыделения: содержимое сдвигается к нулевому слоту,
+    // лишние слоты отрезаются, а их память остается за хранилищем (вернуть
+    // ее можно через shrink_to_fit). Не меньше size + reserved.
+    fn shrink_in_place(&mut self, new_capacity: usize) {
+        debug_assert!(new_capacity >= self.size + self.reserved);
+        // Свободные слоты пусты, поэтому после сдвига все живое в 0..size
+        self.data.0.rotate_left(self.head);
+        self.seqs.rotate_left(self.head);
+        self.data.0.truncate(new_capacity);
+        self.seqs.truncate(new_capacity);
+        self.relayout(new_capacity);
+    }
+
+    // Указатели и индексация после переукладки содержимого с нулевого слота
+    fn relayout(&mut self, new_capacity: usize) {
+        self.record_capacity_change(self.capacity, new_capacity);
+        self.head = 0;
+        self.tail = if new_capacity == 0 {
+            0
+        } else {
+            self.size % new_capacity
+        };
+        self.capacity = new_capacity;
+        // Маска сохраняется, только если новая вместимость тоже степень двойки
+        self.indexing = match self.indexing {
+            Indexing::Mask(_) if new_capacity.is_power_of_two() => Indexing::Mask(MaskStrategy {
+                mask: new_capacity - 1,
+            }),
+            _ => Indexing::modulo(new_capacity),
+        };
+    }
+
+    // Изменение вместимости с сохранением содержимого. Уменьшить вместимость
+    // ниже текущего size (с учетом резерва) нельзя (BufferError::Full).
+    // Уменьшение не перевыделяет память (см. shrink_in_place): частые
+    // resize туда-обратно не гоняют аллокатор, а лишнее снимает shrink_to_fit.
+    fn resize(&mut self, new_capacity: usize) -> Result<(), BufferError> {
+        if new_capacity < self.size + self.reserved {
+            return Err(BufferError::Full {
+                capacity: new_capacity,
+            });
+        }
+        if new_capacity < self.capacity {
+            self.shrink_in_place(new_capacity);
+            return Ok(());
+        }
+        self.reallocate(new_capacity)
+    }
+
+    // Сколько слотов реально выделено в хранилище (не меньше capacity)
+    fn allocated_slots(&self) -> usize {
+        self.data.0.capacity()
+    }
+
+    // Освобождение лишней памяти хранилища: если выделено больше слотов, чем
+    // логическая вместимость (например, после уменьшения через resize),
+    // содержимое переносится в точное по размеру хранилище (с нулевого
+    // слота). Вместимость не меняется. При нехватке памяти буфер остается
+    // прежним.
+    fn shrink_to_fit(&mut self) {
+        if self.allocated_slots() > self.capacity || self.seqs.capacity() > self.capacity {
+            let capacity = self.capacity;
+            let history = self.capacity_history.len();
+            if self.reallocate(capacity).is_ok() {
+                // Вместимость не изменилась — в истории этому не место
+                self.capacity_history.truncate(history);
+            }
+        }
+    }
+
+    // Уменьшение вместимости, если буфер заполнен меньше чем на четверть:
+    // вместимость уменьшается вдвое (но не меньше size и не меньше 1).
+    // Возвращает, было ли уменьшение.
+    fn maybe_shrink(&mut self) -> bool {
+        let used = self.size + self.reserved;
+        if used >= self.capacity.div_ceil(4) {
+            return false;
+        }
+        let new_capacity = (self.capacity / 2).max(used).max(1);
+        if new_capacity >= self.capacity {
+            return false;
+        }
+        // Не удалось выделить память — просто остаемся с прежним буфером
+        self.reallocate(new_capacity).is_ok()
+    }
+
+    // Примерный объем памяти: сама структура плюс слоты на куче — значение
+    // и порядковый номер на каждый слот. Мелкие служебные очереди
+    // (capacity_history, окно push_rate) не учитываются.
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.capacity * (std::mem::size_of::<Option<u8>>() + std::mem::size_of::<u64>())
+    }
+}
+
+// Буфер с вместимостью N, известной при компиляции
+type ArrayRingBuffer<const N: usize> = RingBuffer<ArrayStorage<N>>;
+
+impl<const N: usize> ArrayRingBuffer<N> {
+    // Нулевая вместимость отвергается при компиляции, а не паникой:
+    // ArrayRingBuffer::<0>::new_array() не собирается (E0080, "evaluation
+    // panicked"). Проверяется вручную — compile-fail тесты (trybuild) для
+    // бинарного крейта не подключить.
+    fn new_array() -> Self {
+        const { assert!(N > 0, "ArrayRingBuffer требует N > 0") };
+        RingBuffer::with_storage(ArrayStorage([None; N]))
+    }
+}
+
+impl<S: Storage> RingBuffer<S> {
+    // Буфер поверх готового хранилища; вместимость равна его длине.
+    // Прежнее содержимое слотов сбрасывается.
+    fn with_storage(mut data: S) -> Self {
+        let capacity = data.len();
+        for index in 0..capacity {
+            *data.get_mut(index) = None;
+        }
         RingBuffer {
-            data: vec![None; capacity], // Заполняем None
+            data,
             head: 0,
             tail: 0,
             size: 0,
             capacity,
+            pushed: 0,
+            popped: 0,
+            overwritten: 0,
+            running_sum: 0,
+            indexing: Indexing::modulo(capacity),
+            reserved: 0,
+            capacity_history: VecDeque::new(),
+            push_rate: RateCounter::new(Duration::from_secs(1), PUSH_RATE_WINDOW_SECS),
+            compact_below: None,
+            seqs: vec![0; capacity],
+            next_seq: 0,
+            op_seq: 0,
+            generation: 0,
+            removed_seqs: None,
+        }
+    }
+
+    // Следующий индекс по кольцу
+    fn advance(&self, index: usize) -> usize {
+        self.indexing.advance(index)
+    }
+
+    // Физический слот для логического индекса (0 — head)
+    fn physical(&self, index: usize) -> usize {
+        self.indexing.offset(self.head, index)
+    }
+
+    // Запись в слот; все записи идут через этот метод, чтобы сумма окна
+    // оставалась согласованной с содержимым
+    fn put(&mut self, index: usize, value: u8) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.place(index, value, seq);
+    }
+
+    // Запись с уже выданным номером — для перемещения элемента внутри буфера
+    fn place(&mut self, index: usize, value: u8, seq: u64) {
+        *self.data.get_mut(index) = Some(value);
+        self.seqs[index] = seq;
+        self.running_sum += value as u64;
+    }
+
+    // Извлечение из слота (парный к put): элемент покидает буфер
+    fn take(&mut self, index: usize) -> Option<u8> {
+        let seq = self.seqs[index];
+        let value = self.take_for_move(index);
+        if let (Some(_), Some(removed)) = (value, &mut self.removed_seqs) {
+            removed.push(seq);
         }
+        value
+    }
+
+    // Извлечение из слота для последующего place с тем же номером:
+    // элемент остается в буфере, только меняет слот
+    fn take_for_move(&mut self, index: usize) -> Option<u8> {
+        let value = self.data.get_mut(index).take();
+        if let Some(v) = value {
+            self.running_sum -= v as u64;
+        }
+        value
     }
 
     // Проверка на пустоту
@@ -35,169 +679,5771 @@ impl RingBuffer {
     }
 
     // Проверка на заполненность
+    // Зарезервированные слоты тоже считаются занятыми
     fn is_full(&self) -> bool {
-        self.size == self.capacity
+        self.size + self.reserved >= self.capacity
+    }
+
+    // Количество свободных (не занятых и не зарезервированных) слотов
+    fn free(&self) -> usize {
+        self.capacity - self.size - self.reserved
+    }
+
+    // Текущее количество элементов
+    fn len(&self) -> usize {
+        self.size
+    }
+
+    // Элемент по логическому индексу (0 — самый старый), без извлечения
+    fn get(&self, index: usize) -> Option<u8> {
+        if index >= self.size {
+            return None;
+        }
+        *self.data.get(self.physical(index))
+    }
+
+    // Просмотр первого элемента без извлечения
+    fn peek(&self) -> Option<u8> {
+        self.get(0)
+    }
+
+    // Живые элементы двумя непрерывными отрезками хранилища: от head до конца
+    // массива (или до tail) и, если содержимое переходит через границу,
+    // от начала массива. Второй отрезок пуст, если перехода нет.
+    fn as_slices(&self) -> (&[Option<u8>], &[Option<u8>]) {
+        let first_len = self.size.min(self.capacity - self.head);
+        let slots = self.data.as_slice();
+        let first = &slots[self.head..self.head + first_len];
+        let second = &slots[..self.size - first_len];
+        (first, second)
+    }
+
+    // Наибольший непрерывный отрезок с начала очереди (первый из as_slices)
+    fn contiguous_front(&self) -> &[Option<u8>] {
+        self.as_slices().0
+    }
+
+    // Итератор по элементам в порядке FIFO (без извлечения)
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.size).filter_map(|i| self.get(i))
+    }
+
+    // Пары (логический индекс от head, &значение) в порядке FIFO;
+    // индексы всегда 0..size, независимо от физического расположения
+    fn enumerate_iter(&self) -> impl Iterator<Item = (usize, &u8)> + '_ {
+        (0..self.size).filter_map(move |i| {
+            self.data
+                .get(self.physical(i))
+                .as_ref()
+                .map(|value| (i, value))
+        })
+    }
+
+    // Расхождения с other по логическому содержимому (FIFO), а не по
+    // физическим слотам: (индекс, значение здесь, значение там) для каждого
+    // несовпадающего индекса; у более короткого буфера — None
+    fn diff<T: Storage>(&self, other: &RingBuffer<T>) -> Vec<(usize, Option<u8>, Option<u8>)> {
+        (0..self.size.max(other.size))
+            .map(|i| (i, self.get(i), other.get(i)))
+            .filter(|(_, ours, theirs)| ours != theirs)
+            .collect()
+    }
+
+    // Отпечаток логического содержимого (FNV-1a по элементам в порядке FIFO).
+    // Не зависит от физического расположения, стабилен между запусками.
+    fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        self.iter().fold(FNV_OFFSET, |hash, value| {
+            (hash ^ value as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    // Сумма текущего содержимого, поддерживается инкрементально
+    fn window_sum(&self) -> u64 {
+        self.running_sum
+    }
+
+    // Среднее по текущему содержимому (0.0 для пустого буфера)
+    fn window_avg(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.running_sum as f64 / self.size as f64
     }
 
     // Добавление элемента
     fn push(&mut self, value: u8) -> Result<(), BufferError> {
         if self.is_full() {
-            return Err(BufferError::Full);
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
+        }
+        yield_point!("push");
+
+        self.put(self.tail, value);
+        self.tail = self.advance(self.tail); // Кольцевой буфер
+        self.size += 1;
+        self.pushed += 1;
+        self.push_rate.record(Instant::now());
+        trace_op!(self, "push");
+        Ok(())
+    }
+
+    // Добавление без проверки заполненности — для горячих циклов, где место
+    // уже проверено заранее.
+    // Safety: вызывающий обязан гарантировать !is_full(); иначе нарушаются
+    // инварианты буфера (size > capacity, затирание непрочитанных данных).
+    unsafe fn push_unchecked(&mut self, value: u8) {
+        debug_assert!(!self.is_full(), "push_unchecked в полный буфер");
+        self.put(self.tail, value);
+        self.tail = self.advance(self.tail);
+        self.size += 1;
+        self.pushed += 1;
+        self.push_rate.record(Instant::now());
+    }
+
+    // Пакетное добавление "все или ничего": одна проверка места на весь срез
+    fn push_slice(&mut self, values: &[u8]) -> Result<(), BufferError> {
+        if self.free() < values.len() {
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
+        }
+        for &value in values {
+            // Safety: выше проверено, что места хватает на весь срез
+            unsafe { self.push_unchecked(value) };
+        }
+        trace_op!(self, "push_slice");
+        Ok(())
+    }
+
+    // Добавление в начало (перед head), например чтобы вернуть отложенный элемент
+    fn push_front(&mut self, value: u8) -> Result<(), BufferError> {
+        if self.is_full() {
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
         }
 
-        self.data[self.tail] = Some(value);
-        self.tail = (self.tail + 1) % self.capacity; // Кольцевой буфер
+        // Шаг назад с учетом перехода через границу
+        self.head = if self.head == 0 {
+            self.capacity - 1
+        } else {
+            self.head - 1
+        };
+        self.put(self.head, value);
+        self.size += 1;
+        self.pushed += 1;
+        self.push_rate.record(Instant::now());
+        trace_op!(self, "push_front");
+        Ok(())
+    }
+
+    // Вставка с сохранением порядка по возрастанию (содержимое должно уже
+    // быть отсортировано): value встает после всех элементов <= value,
+    // более крупные сдвигаются на слот к хвосту. Полный буфер — Full.
+    fn insert_sorted(&mut self, value: u8) -> Result<(), BufferError> {
+        if self.is_full() {
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
+        }
+
+        let position = (0..self.size)
+            .find(|&i| self.get(i).is_some_and(|existing| existing > value))
+            .unwrap_or(self.size);
+        // Сдвиг с конца, чтобы не затереть еще не перенесенные элементы
+        for i in (position..self.size).rev() {
+            let (from, to) = (self.physical(i), self.physical(i + 1));
+            let seq = self.seqs[from];
+            let moved = self
+                .take_for_move(from)
+                .expect("слот внутри [head, tail) заполнен");
+            self.place(to, moved, seq);
+        }
+        self.put(self.physical(position), value);
+        self.tail = self.advance(self.tail);
         self.size += 1;
+        self.pushed += 1;
+        self.push_rate.record(Instant::now());
+        trace_op!(self, "insert_sorted");
         Ok(())
     }
 
+    // Добавление с затиранием: если буфер полон, вытесняем самый старый элемент
+    // и возвращаем его
+    fn push_overwrite(&mut self, value: u8) -> Option<u8> {
+        if self.is_full() && self.is_empty() {
+            // Хранить негде (нулевая вместимость или все слоты
+            // зарезервированы) — "вытесняется" сам элемент
+            return Some(value);
+        }
+
+        let evicted = if self.is_full() {
+            let old = self.take(self.head);
+            self.head = self.advance(self.head);
+            self.size -= 1;
+            self.overwritten += 1;
+            old
+        } else {
+            None
+        };
+
+        // Safety: если буфер был полон, один слот только что освобожден
+        unsafe { self.push_unchecked(value) };
+        trace_op!(self, "push_overwrite");
+        evicted
+    }
+
+    // Обобщение push_overwrite: если буфер полон, вытесняются до evict самых
+    // старых элементов (не меньше одного — иначе записать некуда), чтобы
+    // освободить место под всплеск записей. Возвращает вытесненные в
+    // порядке FIFO; если буфер не полон, ничего не вытесняется.
+    fn push_evict_n(&mut self, value: u8, evict: usize) -> Vec<u8> {
+        if self.is_full() && self.is_empty() {
+            // Хранить негде — как и в push_overwrite, "вытесняется" сам элемент
+            return vec![value];
+        }
+
+        let mut evicted = Vec::new();
+        if self.is_full() {
+            for _ in 0..evict.clamp(1, self.size) {
+                evicted.extend(self.take(self.head));
+                self.head = self.advance(self.head);
+                self.size -= 1;
+                self.overwritten += 1;
+            }
+        }
+
+        // Safety: если буфер был полон, минимум один слот только что освобожден
+        unsafe { self.push_unchecked(value) };
+        trace_op!(self, "push_evict_n");
+        evicted
+    }
+
     // Извлечение элемента
     fn pop(&mut self) -> Option<u8> {
         if self.is_empty() {
             return None;
         }
+        yield_point!("pop");
 
-        let value = self.data[self.head].take();
-        self.head = (self.head + 1) % self.capacity; // Кольцевой буфер
+        let value = self.take(self.head);
+        self.head = self.advance(self.head); // Кольцевой буфер
         self.size -= 1;
+        self.popped += 1;
+        self.maybe_compact();
+        trace_op!(self, "pop");
         value
     }
-}
 
-// Потокобезопасная обертка
-#[derive(Debug)]
-struct SafeRingBuffer {
-    inner: Mutex<RingBuffer>, // Защищаем буфер мьютексом
-}
+    // Автоуплотнение после pop: если размер упал ниже порога, содержимое
+    // переносится в начало хранилища, чтобы чтения оставались непрерывными
+    fn maybe_compact(&mut self) {
+        if let Some(fraction) = self.compact_below
+            && (self.size as f64) < fraction * self.capacity as f64
+        {
+            self.compact();
+        }
+    }
 
-impl SafeRingBuffer {
-    fn new(capacity: usize) -> Self {
-        SafeRingBuffer {
-            inner: Mutex::new(RingBuffer::new(capacity)),
+    // Перенос содержимого так, чтобы head оказался в нулевом слоте
+    fn compact(&mut self) {
+        if self.head == 0 {
+            return;
+        }
+        let mut values = Vec::with_capacity(self.size);
+        for i in 0..self.size {
+            let index = self.physical(i);
+            let seq = self.seqs[index];
+            values.extend(self.take_for_move(index).map(|value| (value, seq)));
         }
+        for (index, (value, seq)) in values.into_iter().enumerate() {
+            self.place(index, value, seq);
+        }
+        self.head = 0;
+        self.tail = self.size % self.capacity;
     }
 
-    // Потокобезопасное добавление
-    fn push(&self, value: u8) -> Result<(), BufferError> {
-        let mut buffer = self.inner.lock().unwrap(); // Блокируем доступ
-        buffer.push(value)
-        // Мьютекс автоматически разблокируется при выходе из области видимости
+    // Номер головы: каждая запись получает следующий номер по порядку
+    // записи, так что по номеру видно, тот ли это еще элемент
+    fn head_seq(&self) -> Option<u64> {
+        (!self.is_empty()).then(|| self.seqs[self.head])
     }
 
-    // Потокобезопасное извлечение
-    fn pop(&self) -> Option<u8> {
-        let mut buffer = self.inner.lock().unwrap(); // Блокируем доступ
-        buffer.pop()
+    // Номера живых элементов в порядке FIFO
+    fn live_seqs(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.size).map(|i| self.seqs[self.physical(i)])
     }
-}
 
-fn main() {
-    // Создаем потокобезопасный буфер на 5 элементов
+    // Извлечение головы вместе с ее номером
+    fn pop_seq(&mut self) -> Option<(u64, u8)> {
+        let seq = self.head_seq()?;
+        self.pop().map(|value| (seq, value))
+    }
+
+    // Извлечение всех элементов в порядке FIFO
+    fn drain_all(&mut self) -> Vec<u8> {
+        std::iter::from_fn(|| self.pop()).collect()
+    }
+
+    // Извлечение всех элементов, для которых f истинна, в порядке FIFO.
+    // Оставшиеся сохраняют исходный относительный порядок: каждый элемент
+    // снимается с головы и, если не подошел, возвращается в хвост.
+    fn extract_if<F: FnMut(&u8) -> bool>(&mut self, mut f: F) -> Vec<u8> {
+        let mut extracted = Vec::new();
+        for _ in 0..self.size {
+            let seq = self.seqs[self.head];
+            let value = self
+                .take_for_move(self.head)
+                .expect("слот внутри [head, tail) заполнен");
+            self.head = self.advance(self.head);
+            if f(&value) {
+                extracted.push(value);
+                self.size -= 1;
+                if let Some(removed) = &mut self.removed_seqs {
+                    removed.push(seq);
+                }
+            } else {
+                self.place(self.tail, value, seq);
+                self.tail = self.advance(self.tail);
+            }
+        }
+        self.popped += extracted.len() as u64;
+        extracted
+    }
+
+    // Очистка буфера
+    fn clear(&mut self) {
+        if let Some(mut removed) = self.removed_seqs.take() {
+            // Не дальше вместимости: после отравления size может быть любым
+            removed.extend((0..self.size.min(self.capacity)).map(|i| self.seqs[self.physical(i)]));
+            self.removed_seqs = Some(removed);
+        }
+        for index in 0..self.data.len() {
+            *self.data.get_mut(index) = None;
+        }
+        self.head = 0;
+        self.tail = 0;
+        self.size = 0;
+        self.running_sum = 0;
+        trace_op!(self, "clear");
+    }
+
+    // Полный отчет о внутреннем состоянии для баг-репортов: счетчики,
+    // указатели, все физические слоты (с пометками head и tail) и логическое
+    // содержимое в порядке FIFO
+    fn debug_dump(&self) -> String {
+        let mut out = String::from("RingBuffer dump\n");
+        out.push_str(&format!("  capacity: {}\n", self.capacity));
+        out.push_str(&format!("  size: {}\n", self.size));
+        out.push_str(&format!("  reserved: {}\n", self.reserved));
+        out.push_str(&format!("  head: {}\n", self.head));
+        out.push_str(&format!("  tail: {}\n", self.tail));
+        out.push_str("  slots:\n");
+        for index in 0..self.data.len() {
+            let marks = match (index == self.head, index == self.tail) {
+                (true, true) => " <- head, tail",
+                (true, false) => " <- head",
+                (false, true) => " <- tail",
+                (false, false) => "",
+            };
+            out.push_str(&format!(
+                "    [{index}] {:?}{marks}\n",
+                self.data.get(index)
+            ));
+        }
+        out.push_str(&format!(
+            "  contents: {:?}\n",
+            self.iter().collect::<Vec<_>>()
+        ));
+        out
+    }
+
+    // Проверка внутренней согласованности: указатели в пределах хранилища,
+    // tail сразу за последним элементом, заполнены ровно слоты [head, tail),
+    // сумма окна совпадает с содержимым. Нужна после паники под блокировкой
+    // (см. SafeRingBuffer::clear_poison).
+    fn is_consistent(&self) -> bool {
+        if self.data.len() != self.capacity || self.size + self.reserved > self.capacity {
+            return false;
+        }
+        if self.capacity == 0 {
+            return self.size == 0 && self.head == 0 && self.tail == 0;
+        }
+        if self.head >= self.capacity || self.tail >= self.capacity {
+            return false;
+        }
+        let expected_tail = if self.size == self.capacity {
+            self.head
+        } else {
+            self.physical(self.size)
+        };
+        let live = |index: usize| (0..self.size).any(|i| self.physical(i) == index);
+        let slots_ok =
+            (0..self.capacity).all(|index| self.data.get(index).is_some() == live(index));
+        let sum: u64 = self.iter().map(u64::from).sum();
+        self.tail == expected_tail && slots_ok && sum == self.running_sum
+    }
+
+    // Замена содержимого последними min(capacity, data.len()) байтами data.
+    // Прежние элементы считаются затертыми.
+    fn overwrite_with(&mut self, data: &[u8]) {
+        self.overwritten += self.size as u64;
+        self.clear();
+
+        let start = data.len().saturating_sub(self.free());
+        for &value in &data[start..] {
+            self.push(value).expect("срез обрезан до вместимости");
+        }
+    }
+
+    // Запись в историю вместимости; старые записи вытесняются по кругу.
+    // Перестройка без смены вместимости переходом не считается.
+    fn record_capacity_change(&mut self, old: usize, new: usize) {
+        if old == new {
+            return;
+        }
+        if self.capacity_history.len() == CAPACITY_HISTORY_LEN {
+            self.capacity_history.pop_front();
+        }
+        self.capacity_history.push_back((Instant::now(), old, new));
+    }
+
+    // Извлечение последнего записанного элемента (LIFO), pop остается FIFO
+    fn pop_back(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Шаг назад с учетом перехода через границу
+        self.tail = if self.tail == 0 {
+            self.capacity - 1
+        } else {
+            self.tail - 1
+        };
+        let value = self.take(self.tail);
+        self.size -= 1;
+        self.popped += 1;
+        trace_op!(self, "pop_back");
+        value
+    }
+}
+
+// Из VecDeque: вместимость равна длине очереди, порядок сохраняется
+impl From<VecDeque<u8>> for RingBuffer {
+    fn from(deque: VecDeque<u8>) -> Self {
+        let mut buffer = RingBuffer::new(deque.len());
+        for value in deque {
+            buffer.push(value).expect("вместимость равна длине очереди");
+        }
+        buffer
+    }
+}
+
+// Из (вместимость, данные): буфер заданной вместимости с данными в начале
+// и местом под дальнейшие записи; данные длиннее вместимости — Full
+impl TryFrom<(usize, Vec<u8>)> for RingBuffer {
+    type Error = BufferError;
+
+    fn try_from((capacity, values): (usize, Vec<u8>)) -> Result<Self, BufferError> {
+        if values.len() > capacity {
+            return Err(BufferError::Full { capacity });
+        }
+        RingBuffer::with_capacity_from_iter(capacity, values)
+    }
+}
+
+// В VecDeque: живые элементы в порядке FIFO
+impl From<RingBuffer> for VecDeque<u8> {
+    fn from(buffer: RingBuffer) -> Self {
+        buffer.iter().collect()
+    }
+}
+
+// Потокобезопасная обертка
+#[derive(Debug)]
+struct SafeRingBuffer {
+    inner: Mutex<RingBuffer>,          // Защищаем буфер мьютексом
+    contended_acquisitions: AtomicU64, // Сколько раз пришлось ждать блокировку
+    label: String,                     // Метка для группировки метрик
+    not_full: Condvar,                 // Место освободилось (в т.ч. буфер опустел)
+    not_empty: Condvar,                // Появились данные
+    closed: AtomicBool,                // Буфер закрыт (меняется под блокировкой)
+    #[cfg(debug_assertions)]
+    holder: Mutex<Option<thread::ThreadId>>, // Поток, удерживающий блокировку (debug)
+    sampler: Option<Sampler>,          // Выборочное наблюдение за записями
+    // Датчик заполненности (см. on_occupancy)
+    occupancy: Option<Callback<dyn Fn(f64) + Send + Sync>>,
+    // Проверка значений при записи (см. with_validator)
+    validator: Option<Callback<Validator>>,
+    alternation: Option<Alternation>, // Режим чередования (см. with_alternation)
+    lossy: Option<LossyDrop>,         // Сброс значений при переполнении (см. with_lossy_drop)
+    lock_timing: Option<LockTiming>,  // Учет времени удержания (см. with_lock_timing)
+    strict: StrictMode,               // Реакция на ошибки использования
+    notify_threshold: usize, // С какого размера будить читателей (см. with_notify_threshold)
+    quota: Option<ProducerQuota>, // Квота на производителя (см. with_producer_quota)
+    size_mirror: AtomicUsize, // Копия size для len_relaxed (пишется под блокировкой)
+    // Токены потоков внутри pop_blocking_cancellable (будятся вместе с not_empty)
+    cancel_waiters: Mutex<Vec<Arc<CancelState>>>,
+    spin: Option<AdaptiveSpin>, // Ожидание блокировки на spin_loop (см. with_adaptive_spin)
+    reservoir: Option<Mutex<Reservoir>>, // Выборка по истории записей (см. with_reservoir)
+    cow: Mutex<Option<(u64, Arc<RingBuffer>)>>, // Кэш cow_snapshot и его op_seq
+    watermark: Option<WatermarkWatch>, // Пороги заполненности (см. on_watermark)
+    latency: Option<OpLatency>, // Гистограммы задержек (см. with_latency_tracking)
+    // Резервы, отпущенные PushTicket::drop без блокировки (см. apply_released)
+    released_reservations: AtomicUsize,
+}
+
+// Что делать при ошибке использования буфера: запись в буфер нулевой
+// вместимости или повторный захват блокировки тем же потоком (debug).
+// По умолчанию Panic в debug-сборке (падать сразу) и Error в release.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StrictMode {
+    Panic, // Паника с понятным сообщением
+    Error, // ZeroCapacity / OpError::Reentrant
+}
+
+impl Default for StrictMode {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            StrictMode::Panic
+        } else {
+            StrictMode::Error
+        }
+    }
+}
+
+// Границы и начальное значение адаптивной серии ожидания блокировки
+const MIN_SPINS: usize = 4;
+const MAX_SPINS: usize = 1024;
+const INITIAL_SPINS: usize = 64;
+
+// Адаптивная серия spin_loop перед засыпанием на занятом мьютексе:
+// захват во время серии удваивает ее (держат недолго — крутиться выгодно),
+// неудача уполовинивает (держат долго — лучше сразу спать)
+#[derive(Debug)]
+struct AdaptiveSpin {
+    spins: AtomicUsize,
+}
+
+impl AdaptiveSpin {
+    fn new() -> Self {
+        AdaptiveSpin {
+            spins: AtomicUsize::new(INITIAL_SPINS),
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.spins.load(Ordering::Relaxed)
+    }
+
+    // Итог очередной серии: got_lock — захватили, не дожидаясь засыпания
+    fn record(&self, got_lock: bool) {
+        let current = self.current();
+        let next = if got_lock {
+            current.saturating_mul(2).min(MAX_SPINS)
+        } else {
+            (current / 2).max(MIN_SPINS)
+        };
+        // Гонка с другими потоками не страшна: это лишь эвристика
+        self.spins.store(next, Ordering::Relaxed);
+    }
+}
+
+// Число корзин гистограммы задержек: корзина i — [2^(i-1), 2^i) нс,
+// последняя собирает все, что дольше ~19 часов
+const LATENCY_BUCKETS: usize = 48;
+
+// Гистограмма задержек с логарифмическими корзинами: запись — один
+// fetch_add, точность — в пределах двукратной ширины корзины
+#[derive(Debug)]
+struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    fn new() -> Self {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let index = ((u64::BITS - nanos.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Верхняя граница корзины, в которую попадает p-я доля (0.0..=1.0)
+    // замеров; ноль, если замеров нет
+    fn percentile(&self, p: f64) -> Duration {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((p.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in counts.into_iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(1u64 << index);
+            }
+        }
+        unreachable!("rank не больше общего числа замеров")
+    }
+}
+
+// Задержки push и pop (см. with_latency_tracking)
+#[derive(Debug)]
+struct OpLatency {
+    push: LatencyHistogram,
+    pop: LatencyHistogram,
+}
+
+// Статистика удержания блокировки, в наносекундах
+#[derive(Debug, Default)]
+struct LockTiming {
+    max_nanos: AtomicU64,
+    total_nanos: AtomicU64,
+    holds: AtomicU64, // Сколько отрезков удержания учтено
+}
+
+// Режим с потерями: при переполнении значение отбрасывается, а отчет
+// о потерях уходит в sink не чаще раза в interval
+#[derive(Debug)]
+struct LossyDrop {
+    interval: Duration,
+    dropped: AtomicU64,                  // Всего отброшено значений
+    last_report: Mutex<Option<Instant>>, // Когда sink вызывался последний раз
+    sink: Callback<dyn Fn(u64) + Send + Sync>,
+}
+
+// Квота производителя: доля вместимости и учет живых элементов по
+// производителям. Извлеченные и вытесненные элементы снимаются с учета при
+// освобождении блокировки по журналу RingBuffer::removed_seqs, поэтому
+// проверка квоты при записи — O(1).
+#[derive(Debug)]
+struct ProducerQuota {
+    fraction: f64,
+    book: Mutex<QuotaBook>, // Меняется только под блокировкой буфера
+}
+
+// Владельцы живых элементов (номер элемента -> id производителя) и число
+// живых элементов каждого производителя
+#[derive(Debug, Default)]
+struct QuotaBook {
+    owners: HashMap<u64, u64>,
+    counts: HashMap<u64, usize>,
+}
+
+impl QuotaBook {
+    // Снятие с учета извлеченного элемента (чужие номера — без изменений)
+    fn release(&mut self, seq: u64) {
+        let Some(producer) = self.owners.remove(&seq) else {
+            return;
+        };
+        if let Some(count) = self.counts.get_mut(&producer) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&producer);
+            }
+        }
+    }
+}
+
+// Состояние режима чередования блокирующих читателей и писателей
+#[derive(Debug, Default)]
+struct Alternation {
+    last_was_push: AtomicBool, // Последнее изменение размера — запись (под блокировкой)
+    pending_readers: AtomicUsize, // Потоки внутри pop_blocking
+    pending_writers: AtomicUsize, // Потоки внутри push_blocking
+}
+
+// Пользовательский колбэк; Debug показывает только его наличие
+struct Callback<F: ?Sized>(Box<F>);
+
+// Предикат допустимости значения (см. with_validator)
+type Validator = dyn Fn(&u8) -> bool + Send + Sync;
+
+impl<F: ?Sized> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback(..)")
+    }
+}
+
+// Резервуар для равномерной выборки k значений из всех записей
+#[derive(Debug)]
+struct Reservoir {
+    k: usize,
+    seen: u64, // Сколько записей уже предложено
+    rng: u64,  // Состояние xorshift64
+    values: Vec<u8>,
+}
+
+impl Reservoir {
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    // Шаг алгоритма R: первые k значений берутся как есть, n-е затем
+    // заменяет случайный элемент с вероятностью k/n
+    fn offer(&mut self, value: u8) {
+        self.seen += 1;
+        if self.values.len() < self.k {
+            self.values.push(value);
+            return;
+        }
+        let index = (self.next_random() % self.seen) as usize;
+        if index < self.k {
+            self.values[index] = value;
+        }
+    }
+}
+
+// Какой порог заполненности пересечен (см. on_watermark)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Watermark {
+    Low,  // Размер опустился ниже low
+    High, // Размер поднялся выше high
+}
+
+// Состояние on_watermark: по какую сторону порогов был размер в прошлый раз
+#[derive(Debug)]
+struct WatermarkWatch {
+    low: usize,
+    high: usize,
+    below_low: AtomicBool, // Меняются под блокировкой буфера
+    above_high: AtomicBool,
+    callback: Callback<dyn Fn(Watermark) + Send + Sync>,
+}
+
+impl WatermarkWatch {
+    // Пересечение порога новым размером (вызывается под блокировкой)
+    fn update(&self, size: usize) -> Option<Watermark> {
+        let below = size < self.low;
+        let above = size > self.high;
+        let was_below = self.below_low.swap(below, Ordering::Relaxed);
+        let was_above = self.above_high.swap(above, Ordering::Relaxed);
+        if above && !was_above {
+            Some(Watermark::High)
+        } else if below && !was_below {
+            Some(Watermark::Low)
+        } else {
+            None
+        }
+    }
+}
+
+// Каждая every_n-я успешная запись копируется наблюдателю
+#[derive(Debug)]
+struct Sampler {
+    every_n: u64,
+    pushes: AtomicU64, // Счетчик успешных push для выборки
+    observer: Callback<dyn Fn(u8) + Send + Sync>,
+}
+
+// Токен кооперативной отмены блокирующих операций (pop_blocking_cancellable).
+// Клоны разделяют состояние: cancel из любого потока будит всех, кто ждет
+// с этим токеном. Отмена необратима.
+#[derive(Debug, Clone, Default)]
+struct CancelToken {
+    state: Arc<CancelState>,
+}
+
+// Ждущий с токеном спит на его condvar; будят его и отмена, и буфер
+// (вместо not_empty). Счетчик пробуждений не дает пропустить сигнал между
+// проверкой буфера и засыпанием.
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    wakeups: Mutex<u64>,
+    condvar: Condvar,
+}
+
+impl CancelState {
+    fn wake(&self) {
+        *self.wakeups.lock().expect("мьютекс токена отравлен") += 1;
+        self.condvar.notify_all();
+    }
+}
+
+impl CancelToken {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+        self.state.wake();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// Guard, который отдает SafeRingBuffer::lock
+type BufferGuard<'a> = LockGuard<'a>;
+
+// Захваченная блокировка буфера. При освобождении (уже после разблокировки)
+// будит ожидающих, если размер изменился (писателей — при уменьшении,
+// читателей — при росте), сообщает заполненность колбэку on_occupancy
+// и пересечение порогов колбэку on_watermark.
+// Также сбрасывает владельца, по которому в debug-сборке
+// ловится повторный захват из того же потока.
+struct LockGuard<'a> {
+    guard: Option<MutexGuard<'a, RingBuffer>>, // None только внутри wait
+    owner: &'a SafeRingBuffer,
+    size_at_acquire: usize,     // Размер на момент захвата
+    locked_at: Option<Instant>, // Начало удержания (только с with_lock_timing)
+}
+
+impl<'a> LockGuard<'a> {
+    // Ожидание на условной переменной буфера. Блокировка отпускается на время
+    // ожидания; пробуждение может быть ложным, поэтому вызывающий обязан
+    // перепроверять условие в цикле while. Если за время ожидания мьютекс
+    // отравили, блокировка отпускается и возвращается OpError::Poisoned.
+    fn wait(self, condvar: &Condvar) -> Result<Self, OpError> {
+        self.wait_with(|guard| condvar.wait(guard))
+    }
+
+    // wait, ограниченный по времени: просыпаемся не позже чем через timeout
+    fn wait_timeout(self, condvar: &Condvar, timeout: Duration) -> Result<Self, OpError> {
+        self.wait_with(|guard| {
+            condvar
+                .wait_timeout(guard, timeout)
+                .map(|(guard, _)| guard)
+                .map_err(|poisoned| PoisonError::new(poisoned.into_inner().0))
+        })
+    }
+
+    // Ожидание не дольше deadline; true вторым значением — срок уже вышел
+    // (тогда без ожидания). deadline None — срока нет: Instant::now() + timeout
+    // не представим (например, Duration::MAX), и ждем как в wait.
+    fn wait_until(
+        self,
+        condvar: &Condvar,
+        deadline: Option<Instant>,
+    ) -> Result<(Self, bool), OpError> {
+        let Some(deadline) = deadline else {
+            return Ok((self.wait(condvar)?, false));
+        };
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok((self, true));
+        }
+        Ok((self.wait_timeout(condvar, deadline - now)?, false))
+    }
+
+    fn wait_with(
+        mut self,
+        wait: impl FnOnce(MutexGuard<'a, RingBuffer>) -> LockResult<MutexGuard<'a, RingBuffer>>,
+    ) -> Result<Self, OpError> {
+        let guard = self.guard.take().expect("guard на месте");
+        self.owner.set_holder(None);
+        self.record_hold(); // Время ожидания в удержание не входит
+        let (guard, poisoned) = match wait(guard) {
+            Ok(guard) => (guard, false),
+            Err(poisoned) => (poisoned.into_inner(), true),
+        };
+        self.owner.set_holder(Some(thread::current().id()));
+        if self.locked_at.is_some() {
+            self.locked_at = Some(Instant::now());
+        }
+        self.size_at_acquire = guard.size;
+        self.guard = Some(guard);
+        if poisoned {
+            return Err(OpError::Poisoned); // Гард отпускается обычным drop
+        }
+        Ok(self)
+    }
+}
+
+impl LockGuard<'_> {
+    // Учет завершившегося отрезка удержания блокировки (with_lock_timing)
+    fn record_hold(&self) {
+        if let (Some(timing), Some(locked_at)) = (&self.owner.lock_timing, self.locked_at) {
+            let nanos = locked_at.elapsed().as_nanos() as u64;
+            timing.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+            timing.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+            timing.holds.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Deref for LockGuard<'_> {
+    type Target = RingBuffer;
+
+    fn deref(&self) -> &RingBuffer {
+        self.guard.as_ref().expect("guard на месте")
+    }
+}
+
+// Любой изменяющий доступ через блокировку продвигает op_seq: изменение
+// всегда меняет номер (обратное не обязательно — pop пустого тоже считается)
+impl DerefMut for LockGuard<'_> {
+    fn deref_mut(&mut self) -> &mut RingBuffer {
+        let buffer = self.guard.as_mut().expect("guard на месте");
+        buffer.op_seq += 1;
+        buffer
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        // Владелец сбрасывается до разблокировки мьютекса
+        self.owner.set_holder(None);
+        let Some(mut guard) = self.guard.take() else {
+            return;
+        };
+        let released = self.owner.apply_released(&mut guard);
+        self.owner.settle_quota(&mut guard);
+        let (size, capacity) = (guard.size, guard.capacity);
+        self.owner.size_mirror.store(size, Ordering::Relaxed);
+        let crossed = self
+            .owner
+            .watermark
+            .as_ref()
+            .and_then(|watch| watch.update(size));
+        self.record_hold();
+        if let Some(alternation) = &self.owner.alternation
+            && size != self.size_at_acquire
+        {
+            let grew = size > self.size_at_acquire;
+            alternation.last_was_push.store(grew, Ordering::Relaxed);
+        }
+        drop(guard); // Разблокируем до уведомлений и колбэков
+
+        if size < self.size_at_acquire || released {
+            self.owner.not_full.notify_all();
+        }
+        if size > self.size_at_acquire && size >= self.owner.notify_threshold {
+            self.owner.wake_readers();
+        }
+        if size != self.size_at_acquire
+            && let Some(occupancy) = &self.owner.occupancy
+        {
+            let fraction = if capacity == 0 {
+                0.0
+            } else {
+                size as f64 / capacity as f64
+            };
+            (occupancy.0)(fraction);
+        }
+        if let (Some(watch), Some(crossed)) = (&self.owner.watermark, crossed) {
+            (watch.callback.0)(crossed);
+        }
+    }
+}
+
+impl SafeRingBuffer {
+    fn new(capacity: usize) -> Self {
+        Self::named(capacity, "")
+    }
+
+    // Буфер с меткой, которая попадает в Display и метрики
+    fn named(capacity: usize, label: impl Into<String>) -> Self {
+        Self::from_ring(RingBuffer::new(capacity), label.into())
+    }
+
+    // Конструктор с проверкой: нулевая вместимость или нехватка памяти — ошибка
+    fn try_new(capacity: usize) -> Result<Self, BufferError> {
+        let ring = RingBuffer::try_with_capacity(capacity)?;
+        Ok(Self::from_ring(ring, String::new()))
+    }
+
+    fn from_ring(ring: RingBuffer, label: String) -> Self {
+        SafeRingBuffer {
+            size_mirror: AtomicUsize::new(ring.size),
+            inner: Mutex::new(ring),
+            contended_acquisitions: AtomicU64::new(0),
+            label,
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+            closed: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            holder: Mutex::new(None),
+            sampler: None,
+            occupancy: None,
+            validator: None,
+            alternation: None,
+            lossy: None,
+            lock_timing: None,
+            strict: StrictMode::default(),
+            notify_threshold: 1,
+            quota: None,
+            cancel_waiters: Mutex::new(Vec::new()),
+            spin: None,
+            reservoir: None,
+            cow: Mutex::new(None),
+            watermark: None,
+            latency: None,
+            released_reservations: AtomicUsize::new(0),
+        }
+    }
+
+    // Объединение пробуждений: рост размера будит ждущих читателей, только
+    // когда size достиг threshold (или по flush). Для пакетных потребителей,
+    // которым не важна задержка: меньше пробуждений на мелких записях.
+    // Закрытие будит всегда.
+    fn with_notify_threshold(mut self, threshold: usize) -> Self {
+        self.notify_threshold = threshold.max(1);
+        self
+    }
+
+    // Квота на производителя для push_from: ни один производитель не занимает
+    // больше fraction * capacity слотов (округление вниз), лишние записи —
+    // QuotaExceeded, даже если место в буфере есть. Квота освобождается,
+    // когда элементы производителя извлекаются любым способом.
+    fn with_producer_quota(mut self, fraction: f64) -> Self {
+        self.quota = Some(ProducerQuota {
+            fraction: fraction.clamp(0.0, 1.0),
+            book: Mutex::new(QuotaBook::default()),
+        });
+        self.inner
+            .get_mut()
+            .expect("SafeRingBuffer: мьютекс отравлен")
+            .removed_seqs = Some(Vec::new());
+        self
+    }
+
+    // Запись от имени производителя producer; без with_producer_quota —
+    // то же, что push
+    fn push_from(&self, producer: u64, value: u8) -> Result<(), BufferError> {
+        self.push_owned(Some(producer), value)
+    }
+
+    // Снятие с учета квоты элементов из журнала removed_seqs. Вызывается
+    // под блокировкой буфера при ее освобождении (LockGuard::drop).
+    fn settle_quota(&self, buffer: &mut RingBuffer) {
+        let Some(removed) = &mut buffer.removed_seqs else {
+            return;
+        };
+        if removed.is_empty() {
+            return;
+        }
+        let Some(quota) = &self.quota else {
+            // Копия буфера с квотой (deep_copy): учитывать некому
+            removed.clear();
+            return;
+        };
+        // Без паники в drop даже при отравленном мьютексе квот
+        let mut book = quota.book.lock().unwrap_or_else(PoisonError::into_inner);
+        for seq in removed.drain(..) {
+            book.release(seq);
+        }
+    }
+
+    // Принудительное пробуждение читателей независимо от порога
+    fn flush(&self) {
+        let _buffer = self.acquire(); // Чтобы не разминуться с входящим в wait
+        self.wake_readers();
+    }
+
+    // Пробуждение читателей: ждущих на not_empty и с токенами отмены
+    fn wake_readers(&self) {
+        self.not_empty.notify_all();
+        for state in self
+            .cancel_waiters
+            .lock()
+            .expect("мьютекс токенов отравлен")
+            .iter()
+        {
+            state.wake();
+        }
+    }
+
+    // Реакция на ошибки использования (см. StrictMode)
+    fn with_strict_mode(mut self, mode: StrictMode) -> Self {
+        self.strict = mode;
+        self
+    }
+
+    // Запись в буфер нулевой вместимости — ошибка использования:
+    // ZeroCapacity или паника в зависимости от StrictMode
+    fn check_capacity(&self, buffer: &RingBuffer) -> Result<(), BufferError> {
+        if buffer.capacity > 0 {
+            return Ok(());
+        }
+        match self.strict {
+            StrictMode::Panic => panic!("push into a zero-capacity SafeRingBuffer"),
+            StrictMode::Error => Err(BufferError::ZeroCapacity),
+        }
+    }
+
+    // Перед засыпанием на занятом мьютексе поток крутит spin_loop, повторяя
+    // try_lock. Длина серии подстраивается под недавнюю конкуренцию
+    // (см. AdaptiveSpin), текущую показывает current_spin_count.
+    fn with_adaptive_spin(mut self) -> Self {
+        self.spin = Some(AdaptiveSpin::new());
+        self
+    }
+
+    // Гистограммы задержек push и pop: от начала операции (включая ожидание
+    // мьютекса) до освобождения блокировки, без колбэков. По умолчанию
+    // выключено — тогда нет ни замеров времени, ни гистограмм.
+    fn with_latency_tracking(mut self) -> Self {
+        self.latency = Some(OpLatency {
+            push: LatencyHistogram::new(),
+            pop: LatencyHistogram::new(),
+        });
+        self
+    }
+
+    // Перцентиль задержки push (p от 0.0 до 1.0, например 0.99) с точностью
+    // до корзины; ноль, если учет выключен или замеров нет
+    fn push_latency_percentile(&self, p: f64) -> Duration {
+        self.latency
+            .as_ref()
+            .map_or(Duration::ZERO, |latency| latency.push.percentile(p))
+    }
+
+    // То же для pop
+    fn pop_latency_percentile(&self, p: f64) -> Duration {
+        self.latency
+            .as_ref()
+            .map_or(Duration::ZERO, |latency| latency.pop.percentile(p))
+    }
+
+    // Текущая длина серии ожидания (ноль, если with_adaptive_spin не включен)
+    fn current_spin_count(&self) -> usize {
+        self.spin.as_ref().map_or(0, AdaptiveSpin::current)
+    }
+
+    // Серия spin_loop с повторами try_lock; None — мьютекс так и не освободился
+    fn spin_lock(&self, spin: &AdaptiveSpin) -> Option<MutexGuard<'_, RingBuffer>> {
+        for _ in 0..spin.current() {
+            std::hint::spin_loop();
+            match self.inner.try_lock() {
+                Ok(guard) => {
+                    spin.record(true);
+                    return Some(guard);
+                }
+                // Отравление обработает блокирующий lock
+                Err(TryLockError::Poisoned(_)) => return None,
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        spin.record(false);
+        None
+    }
+
+    // Учет времени удержания блокировки — от захвата до освобождения
+    // (ожидание в wait не считается). Помогает найти тяжелую работу под
+    // блокировкой, например медленную функцию в transaction.
+    fn with_lock_timing(mut self) -> Self {
+        self.lock_timing = Some(LockTiming::default());
+        self
+    }
+
+    // Самое долгое удержание (ноль, если учет выключен или захватов не было)
+    fn max_lock_hold(&self) -> Duration {
+        self.lock_timing.as_ref().map_or(Duration::ZERO, |timing| {
+            Duration::from_nanos(timing.max_nanos.load(Ordering::Relaxed))
+        })
+    }
+
+    // Среднее удержание по всем учтенным захватам
+    fn avg_lock_hold(&self) -> Duration {
+        self.lock_timing.as_ref().map_or(Duration::ZERO, |timing| {
+            let holds = timing.holds.load(Ordering::Relaxed);
+            if holds == 0 {
+                return Duration::ZERO;
+            }
+            Duration::from_nanos(timing.total_nanos.load(Ordering::Relaxed) / holds)
+        })
+    }
+
+    // Режим с потерями для сбора метрик: push в полный буфер не ошибается,
+    // а отбрасывает новое значение и увеличивает dropped_total. sink
+    // получает текущий dropped_total не чаще раза в interval, чтобы
+    // устойчивое переполнение не засыпало лог.
+    fn with_lossy_drop(
+        mut self,
+        interval: Duration,
+        sink: impl Fn(u64) + Send + Sync + 'static,
+    ) -> Self {
+        self.lossy = Some(LossyDrop {
+            interval,
+            dropped: AtomicU64::new(0),
+            last_report: Mutex::new(None),
+            sink: Callback(Box::new(sink)),
+        });
+        self
+    }
+
+    // Сколько значений отброшено в режиме с потерями (0 вне этого режима)
+    fn dropped_total(&self) -> u64 {
+        self.lossy
+            .as_ref()
+            .map_or(0, |lossy| lossy.dropped.load(Ordering::Relaxed))
+    }
+
+    // Автоуплотнение: после pop, опустившего размер ниже fraction от
+    // вместимости, голова переносится в нулевой слот (as_slices и
+    // contiguous_front снова отдают все содержимое одним отрезком).
+    // Перенос стоит O(size), поэтому по умолчанию выключен.
+    fn with_auto_compact(mut self, fraction: f64) -> Self {
+        self.inner
+            .get_mut()
+            .expect("SafeRingBuffer: мьютекс отравлен")
+            .compact_below = Some(fraction);
+        self
+    }
+
+    // Режим чередования: если ждут и читатели (pop_blocking), и писатели
+    // (push_blocking), поток той же стороны, что сделала последнюю операцию,
+    // уступает очередь противоположной. Уступают, только когда другая сторона
+    // действительно может продвинуться (есть данные или место), поэтому
+    // взаимоблокировки нет. Неблокирующие операции в очереди не участвуют.
+    fn with_alternation(mut self) -> Self {
+        self.alternation = Some(Alternation::default());
+        self
+    }
+
+    // Писатель уступает, если последней была запись, а читатели ждут данных
+    fn writer_should_yield(&self, buffer: &RingBuffer) -> bool {
+        self.alternation.as_ref().is_some_and(|alternation| {
+            alternation.last_was_push.load(Ordering::Relaxed)
+                && alternation.pending_readers.load(Ordering::Relaxed) > 0
+                && !buffer.is_empty()
+        })
+    }
+
+    // Читатель уступает, если последним было извлечение, а писатели ждут места
+    fn reader_should_yield(&self, buffer: &RingBuffer) -> bool {
+        self.alternation.as_ref().is_some_and(|alternation| {
+            !alternation.last_was_push.load(Ordering::Relaxed)
+                && alternation.pending_writers.load(Ordering::Relaxed) > 0
+                && !buffer.is_full()
+        })
+    }
+
+    // Валидатор значений: все записывающие методы с Result (push, push_front,
+    // push_slice, try_push, begin_push, swap_contents, broadcast и т.д.)
+    // отклоняют значения, для которых f возвращает false, с
+    // BufferError::Invalid. Проверка идет до проверки заполненности
+    // (намеренно): некорректное значение получает Invalid, даже если буфер
+    // полон. Буфер при этом не меняется.
+    // Пакетные методы (push_slice_overwrite, overwrite_with, fill_pattern)
+    // проверяют весь вход заранее: при первом же некорректном байте
+    // возвращается Invalid и ничего не пишется.
+    fn with_validator(mut self, f: impl Fn(&u8) -> bool + Send + Sync + 'static) -> Self {
+        self.validator = Some(Callback(Box::new(f)));
+        self
+    }
+
+    fn validate(&self, value: u8) -> Result<(), BufferError> {
+        match &self.validator {
+            Some(validator) if !(validator.0)(&value) => Err(BufferError::Invalid),
+            _ => Ok(()),
+        }
+    }
+
+    // Проверка всего среза до записи: Invalid, если хоть одно значение
+    // отклонено валидатором
+    fn validate_all(&self, values: &[u8]) -> Result<(), BufferError> {
+        values.iter().try_for_each(|&value| self.validate(value))
+    }
+
+    // Колбэк заполненности: после каждой операции, изменившей размер, получает
+    // size / capacity (0.0..=1.0). Вызывается после обновления размера и
+    // освобождения блокировки, поэтому должен быть дешевым, но может
+    // обращаться к буферу.
+    fn on_occupancy(mut self, f: impl Fn(f64) + Send + Sync + 'static) -> Self {
+        self.occupancy = Some(Callback(Box::new(f)));
+        self
+    }
+
+    // Колбэк порогов для подстройки производителя: High — размер стал больше
+    // high, Low — меньше low. Срабатывает по фронту: один раз на каждое
+    // пересечение, а не на каждую операцию за порогом. Колебания внутри
+    // [low, high] ничего не вызывают. Как и on_occupancy, вызывается после
+    // освобождения блокировки.
+    fn on_watermark(
+        mut self,
+        low: usize,
+        high: usize,
+        f: impl Fn(Watermark) + Send + Sync + 'static,
+    ) -> Self {
+        let size = self
+            .inner
+            .get_mut()
+            .expect("SafeRingBuffer: мьютекс отравлен")
+            .size;
+        self.watermark = Some(WatermarkWatch {
+            low,
+            high,
+            below_low: AtomicBool::new(size < low),
+            above_high: AtomicBool::new(size > high),
+            callback: Callback(Box::new(f)),
+        });
+        self
+    }
+
+    // Наблюдатель, получающий копию каждого every_n-го успешно записанного
+    // через push значения (every_n = 0 трактуется как 1). Нумерация записей
+    // атомарна, поэтому выборка корректна и при конкурентных писателях.
+    // Колбэк вызывается вне блокировки буфера.
+    fn with_sampler(mut self, every_n: usize, f: impl Fn(u8) + Send + Sync + 'static) -> Self {
+        self.sampler = Some(Sampler {
+            every_n: every_n.max(1) as u64,
+            pushes: AtomicU64::new(0),
+            observer: Callback(Box::new(f)),
+        });
+        self
+    }
+
+    // Запоминаем поток-владелец блокировки (только в debug-сборке)
+    fn set_holder(&self, holder: Option<thread::ThreadId>) {
+        #[cfg(debug_assertions)]
+        {
+            *self.holder.lock().unwrap() = holder;
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = holder;
+    }
+
+    // Захват мьютекса; паникует, если мьютекс отравлен
+    fn acquire(&self) -> LockGuard<'_> {
+        match self.try_acquire() {
+            Ok(guard) => guard,
+            // Безошибочным методам вернуть ошибку некуда
+            Err(OpError::Reentrant) => panic!("reentrant lock on SafeRingBuffer"),
+            Err(_) => panic!("SafeRingBuffer: мьютекс отравлен"),
+        }
+    }
+
+    // Захват мьютекса: сначала try_lock, и только если занято — блокирующий lock.
+    // Неудачный try_lock учитывается как конкурентный захват.
+    // В debug-сборке повторный захват тем же потоком (например, из колбэка
+    // transaction) вызывает панику вместо тихой взаимоблокировки.
+    fn try_acquire(&self) -> Result<LockGuard<'_>, OpError> {
+        #[cfg(debug_assertions)]
+        if *self.holder.lock().unwrap() == Some(thread::current().id()) {
+            match self.strict {
+                StrictMode::Panic => panic!("reentrant lock on SafeRingBuffer"),
+                StrictMode::Error => return Err(OpError::Reentrant),
+            }
+        }
+
+        let result = match self.inner.try_lock() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(e)) => Err(e),
+            Err(TryLockError::WouldBlock) => {
+                self.contended_acquisitions.fetch_add(1, Ordering::Relaxed);
+                match self.spin.as_ref().and_then(|spin| self.spin_lock(spin)) {
+                    Some(guard) => Ok(guard),
+                    None => self.inner.lock(),
+                }
+            }
+        };
+        let mut guard = result.map_err(|_| OpError::Poisoned)?;
+        if self.apply_released(&mut guard) {
+            self.not_full.notify_all();
+        }
+
+        self.set_holder(Some(thread::current().id()));
+        Ok(LockGuard {
+            size_at_acquire: guard.size,
+            guard: Some(guard),
+            owner: self,
+            locked_at: self.lock_timing.as_ref().map(|_| Instant::now()),
+        })
+    }
+
+    // Снимает резервы, отпущенные release_reserved с момента прошлого захвата.
+    // Вызывается под блокировкой; true, если место освободилось.
+    fn apply_released(&self, buffer: &mut RingBuffer) -> bool {
+        let released = self.released_reservations.swap(0, Ordering::Relaxed);
+        // Больше резервов, чем есть, отпустить нельзя; в release-сборке
+        // не уходим в переполнение, а обнуляем счетчик
+        buffer.reserved = match buffer.reserved.checked_sub(released) {
+            Some(reserved) => reserved,
+            None => {
+                debug_assert!(false, "отпущено больше резервов, чем есть");
+                0
+            }
+        };
+        released > 0
+    }
+
+    // Явная блокировка для нескольких операций подряд: guard разыменовывается
+    // в &mut RingBuffer, блокировка освобождается при drop (с обычными
+    // уведомлениями ждущих). Отравленный мьютекс — Err(OpError::Poisoned).
+    // Пока guard жив, этот же поток не должен обращаться к буферу через
+    // методы SafeRingBuffer (см. transaction).
+    fn lock(&self) -> Result<BufferGuard<'_>, OpError> {
+        self.try_acquire()
+    }
+
+    // Восстановление после паники другого потока под блокировкой: снимает
+    // отравление и возвращает копию восстановленного состояния. Данные
+    // обычно целы (паника случилась между операциями), и тогда остаются как
+    // есть; если же инварианты нарушены (паника посреди изменения), буфер
+    // очищается. На неотравленном буфере просто возвращает копию.
+    // Резервы живых PushTicket при очистке сохраняются (в пределах
+    // вместимости), чтобы их commit и drop не потеряли свое место.
+    fn clear_poison(&self) -> RingBuffer {
+        self.inner.clear_poison();
+        // Дальше — обычный захват: сброс идет через LockGuard, поэтому
+        // op_seq растет (cow_snapshot не отдаст старую копию), а size_mirror
+        // и уведомления обновляются при освобождении
+        let mut buffer = self.acquire();
+        if !buffer.is_consistent() {
+            let reserved = buffer.reserved.min(buffer.capacity);
+            buffer.clear();
+            buffer.reserved = reserved;
+            // Учет владельцев испорченного содержимого больше не верен
+            if let Some(quota) = &self.quota {
+                *quota.book.lock().unwrap_or_else(PoisonError::into_inner) = QuotaBook::default();
+                buffer.removed_seqs = Some(Vec::new());
+            }
+        }
+        buffer.clone()
+    }
+
+    // Разделяемая ссылка на тот же буфер: данные и мьютекс общие
+    fn share(self: &Arc<Self>) -> Arc<Self> {
+        Arc::clone(self)
+    }
+
+    // Независимая копия: новый мьютекс и скопированное текущее содержимое.
+    // Изменения оригинала и копии друг на друга не влияют.
+    fn deep_copy(&self) -> SafeRingBuffer {
+        let copy = self.acquire().clone();
+        Self::from_ring(copy, self.label.clone())
+    }
+
+    // Сколько захватов блокировки пришлось ждать
+    fn contention_count(&self) -> u64 {
+        self.contended_acquisitions.load(Ordering::Relaxed)
+    }
+
+    // Текущее количество элементов
+    fn len(&self) -> usize {
+        self.acquire().len()
+    }
+
+    // Размер без блокировки — для мониторинга, не мешающего производителям.
+    // Обновляется при каждом освобождении блокировки, поэтому может на
+    // мгновение отставать от len(); для решений о push/pop не годится.
+    fn len_relaxed(&self) -> usize {
+        self.size_mirror.load(Ordering::Relaxed)
+    }
+
+    // Проверка на пустоту
+    fn is_empty(&self) -> bool {
+        self.acquire().is_empty()
+    }
+
+    // Закрытие буфера: try_push начинает возвращать OpError::Closed,
+    // все ожидающие потоки просыпаются и перепроверяют свои условия.
+    // Уже записанные данные можно дочитать.
+    fn close(&self) {
+        let _buffer = self.acquire();
+        self.closed.store(true, Ordering::Relaxed);
+        self.not_full.notify_all();
+        self.wake_readers();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    // Ожидание, пока потребители не вычитают буфер до конца (для
+    // координации завершения работы). Закрытие не прерывает ожидание,
+    // пока в буфере остаются данные: закрытый буфер по-прежнему дочитывается.
+    fn wait_until_empty(&self) {
+        let mut buffer = self.acquire();
+        while !buffer.is_empty() {
+            buffer = buffer
+                .wait(&self.not_full)
+                .expect("SafeRingBuffer: мьютекс отравлен");
+        }
+    }
+
+    // Потокобезопасное добавление
+    fn push(&self, value: u8) -> Result<(), BufferError> {
+        self.push_owned(None, value)
+    }
+
+    // Общий путь push и push_from: producer учитывается в квоте
+    // (with_producer_quota), если она включена
+    fn push_owned(&self, producer: Option<u64>, value: u8) -> Result<(), BufferError> {
+        self.validate(value)?;
+        let started = self.latency.as_ref().map(|_| Instant::now());
+        let mut buffer = self.acquire(); // Блокируем доступ
+        self.check_capacity(&buffer)?;
+        let mut quota = match (&self.quota, producer) {
+            (Some(quota), Some(producer)) => {
+                let book = quota.book.lock().expect("мьютекс квот отравлен");
+                let limit = (buffer.capacity as f64 * quota.fraction).floor() as usize;
+                if book.counts.get(&producer).copied().unwrap_or(0) >= limit {
+                    return Err(BufferError::QuotaExceeded { producer });
+                }
+                Some((book, producer))
+            }
+            _ => None,
+        };
+        let pushed = buffer.push(value);
+        if let (Ok(()), Some((book, producer))) = (&pushed, &mut quota) {
+            book.owners.insert(buffer.next_seq - 1, *producer);
+            *book.counts.entry(*producer).or_default() += 1;
+        }
+        drop(quota); // LockGuard::drop сам берет мьютекс квот
+        drop(buffer); // Разблокируем до вызова пользовательских колбэков
+        if let (Some(latency), Some(started)) = (&self.latency, started) {
+            latency.push.record(started.elapsed());
+        }
+        if let (Err(BufferError::Full { .. }), Some(lossy)) = (&pushed, &self.lossy) {
+            let dropped = lossy.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            let now = Instant::now();
+            let mut last_report = lossy.last_report.lock().expect("мьютекс отчета отравлен");
+            if last_report.is_none_or(|last| now.duration_since(last) >= lossy.interval) {
+                *last_report = Some(now);
+                drop(last_report);
+                (lossy.sink.0)(dropped);
+            }
+            return Ok(());
+        }
+        pushed?;
+        self.observe(value);
+        Ok(())
+    }
+
+    // Учет успешной записи наблюдателями: каждая every_n-я уходит в
+    // with_sampler, каждая — в резервуар with_reservoir
+    // (вызывается уже без блокировки). Зовется из всех записывающих методов
+    // SafeRingBuffer, кроме прямого доступа к RingBuffer (lock, transaction).
+    fn observe(&self, value: u8) {
+        if let Some(sampler) = &self.sampler {
+            let n = sampler.pushes.fetch_add(1, Ordering::Relaxed) + 1;
+            if n % sampler.every_n == 0 {
+                (sampler.observer.0)(value);
+            }
+        }
+        if let Some(reservoir) = &self.reservoir {
+            reservoir
+                .lock()
+                .expect("мьютекс резервуара отравлен")
+                .offer(value);
+        }
+    }
+
+    // observe для каждого записанного значения по порядку
+    fn observe_all(&self, values: &[u8]) {
+        for &value in values {
+            self.observe(value);
+        }
+    }
+
+    // Равномерная выборка из k значений по всей истории записей (алгоритм R),
+    // без хранения самой истории. Учитываются те же записи, что и в
+    // with_sampler. ГСЧ детерминирован: одинаковый seed и одинаковая
+    // последовательность записей дают одинаковую выборку.
+    fn with_reservoir(mut self, k: usize, seed: u64) -> Self {
+        self.reservoir = Some(Mutex::new(Reservoir {
+            k,
+            seen: 0,
+            rng: seed.max(1), // xorshift вырождается на нуле
+            values: Vec::with_capacity(k),
+        }));
+        self
+    }
+
+    // Текущая выборка резервуара (пусто, если with_reservoir не включен)
+    fn sample(&self) -> Vec<u8> {
+        self.reservoir.as_ref().map_or_else(Vec::new, |reservoir| {
+            reservoir
+                .lock()
+                .expect("мьютекс резервуара отравлен")
+                .values
+                .clone()
+        })
+    }
+
+    // Запись с отчетом о запасе: Ok(число свободных слотов после записи),
+    // чтобы производитель мог подобрать размер следующего пакета.
+    // В режиме with_lossy_drop переполнение здесь — все равно Full.
+    fn push_checked(&self, value: u8) -> Result<usize, BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        self.check_capacity(&buffer)?;
+        buffer.push(value)?;
+        let remaining = buffer.free();
+        drop(buffer);
+        self.observe(value);
+        Ok(remaining)
+    }
+
+    // Число свободных слотов (capacity - len - резерв) под одной блокировкой
+    fn remaining(&self) -> usize {
+        self.acquire().free()
+    }
+
+    // Потокобезопасное извлечение
+    fn pop(&self) -> Option<u8> {
+        let started = self.latency.as_ref().map(|_| Instant::now());
+        let mut buffer = self.acquire(); // Блокируем доступ
+        let value = buffer.pop();
+        drop(buffer);
+        if let (Some(latency), Some(started)) = (&self.latency, started) {
+            latency.pop.record(started.elapsed());
+        }
+        value
+    }
+
+    // Извлечение с адаптивным ожиданием: пока буфер пуст, крутимся на
+    // spin_loop с удваивающейся серией, всего не более max_spins итераций.
+    // Промежуточный вариант между активным опросом и блокировкой.
+    fn pop_spin(&self, max_spins: usize) -> Option<u8> {
+        let mut spent = 0;
+        let mut series = 1usize;
+        loop {
+            if let Some(value) = self.pop() {
+                return Some(value);
+            }
+            if spent >= max_spins {
+                return None;
+            }
+            let spins = series.min(max_spins - spent);
+            for _ in 0..spins {
+                std::hint::spin_loop();
+            }
+            spent += spins;
+            series = series.saturating_mul(2);
+        }
+    }
+
+    // Блокирующее извлечение: ждет появления данных. OpError::Closed —
+    // буфер закрыт и пуст, Poisoned — мьютекс отравлен (в том числе пока ждали).
+    fn pop_blocking(&self) -> Result<u8, OpError> {
+        if let Some(alternation) = &self.alternation {
+            alternation.pending_readers.fetch_add(1, Ordering::Relaxed);
+        }
+        let result = self.try_acquire().and_then(|mut buffer| {
+            while (buffer.is_empty() || self.reader_should_yield(&buffer)) && !self.is_closed() {
+                buffer = buffer.wait(&self.not_empty)?;
+            }
+            let value = buffer.pop().ok_or(OpError::Closed)?;
+            Ok((buffer, value))
+        });
+        if let Some(alternation) = &self.alternation {
+            alternation.pending_readers.fetch_sub(1, Ordering::Relaxed);
+        }
+        // Блокировка отпускается уже после выхода из числа ждущих читателей
+        result.map(|(_buffer, value)| value)
+    }
+
+    // pop_blocking с отменой: None, если token отменен (сразу, даже без
+    // данных) или буфер закрыт и пуст. В режиме with_alternation читатель
+    // с токеном очередь не уступает.
+    fn pop_blocking_cancellable(&self, token: &CancelToken) -> Option<u8> {
+        let state = Arc::clone(&token.state);
+        let mut waiters = self
+            .cancel_waiters
+            .lock()
+            .expect("мьютекс токенов отравлен");
+        waiters.push(Arc::clone(&state));
+        drop(waiters);
+
+        let value = loop {
+            let seen = *state.wakeups.lock().expect("мьютекс токена отравлен");
+            if token.is_cancelled() {
+                break None;
+            }
+            let mut buffer = self.acquire();
+            if let Some(value) = buffer.pop() {
+                break Some(value);
+            }
+            if self.is_closed() {
+                break None;
+            }
+            drop(buffer);
+            let mut wakeups = state.wakeups.lock().expect("мьютекс токена отравлен");
+            while *wakeups == seen {
+                wakeups = state
+                    .condvar
+                    .wait(wakeups)
+                    .expect("мьютекс токена отравлен");
+            }
+        };
+
+        let mut waiters = self
+            .cancel_waiters
+            .lock()
+            .expect("мьютекс токенов отравлен");
+        if let Some(index) = waiters.iter().position(|s| Arc::ptr_eq(s, &state)) {
+            waiters.swap_remove(index); // Только свою запись: токен может ждать не один поток
+        }
+        value
+    }
+
+    // Фоновый поток отложенной записи: пакеты до max_batch значений уходят
+    // в flush, как только набралось max_batch или прошел interval с начала
+    // очередного ожидания (тогда — сколько есть; пустые пакеты не
+    // отправляются). После close поток дописывает остаток и завершается.
+    // flush вызывается вне блокировки.
+    fn spawn_flusher(
+        self: Arc<Self>,
+        max_batch: usize,
+        interval: Duration,
+        flush: impl Fn(Vec<u8>) + Send + 'static,
+    ) -> thread::JoinHandle<()> {
+        let max_batch = max_batch.max(1);
+        thread::spawn(move || {
+            loop {
+                // Непредставимый interval (Duration::MAX) — только по размеру
+                let deadline = Instant::now().checked_add(interval);
+                let mut buffer = self.acquire();
+                while buffer.len() < max_batch && !self.is_closed() {
+                    let timed_out;
+                    (buffer, timed_out) = buffer
+                        .wait_until(&self.not_empty, deadline)
+                        .expect("SafeRingBuffer: мьютекс отравлен");
+                    if timed_out {
+                        break;
+                    }
+                }
+                let batch: Vec<u8> = (0..max_batch).map_while(|_| buffer.pop()).collect();
+                let done = self.is_closed() && buffer.is_empty();
+                drop(buffer);
+                if !batch.is_empty() {
+                    flush(batch);
+                }
+                if done {
+                    return;
+                }
+            }
+        })
+    }
+
+    // pop_blocking, ограниченный по времени: OpError::LockTimeout — за
+    // timeout данных не появилось, Closed — буфер закрыт и пуст, Poisoned —
+    // мьютекс отравлен. В режиме with_alternation читатель с таймаутом
+    // очередь не уступает.
+    fn pop_timeout(&self, timeout: Duration) -> Result<u8, OpError> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut buffer = self.try_acquire()?;
+        while buffer.is_empty() && !self.is_closed() {
+            let timed_out;
+            (buffer, timed_out) = buffer.wait_until(&self.not_empty, deadline)?;
+            if timed_out {
+                return Err(OpError::LockTimeout);
+            }
+        }
+        buffer.pop().ok_or(OpError::Closed)
+    }
+
+    // Ожидание данных под блокировкой для блокирующих чтений: возвращает
+    // непустой буфер, Closed — если он закрыт и пуст, Poisoned — при
+    // отравленном мьютексе
+    fn wait_readable(&self) -> Result<LockGuard<'_>, OpError> {
+        let mut buffer = self.try_acquire()?;
+        while buffer.is_empty() && !self.is_closed() {
+            buffer = buffer.wait(&self.not_empty)?;
+        }
+        if buffer.is_empty() {
+            return Err(OpError::Closed);
+        }
+        Ok(buffer)
+    }
+
+    // Ожидание непустого буфера и вычитывание всего, что есть, под одной
+    // блокировкой. Закрытый и пустой буфер — OpError::Closed.
+    fn drain_all_blocking(&self) -> Result<Vec<u8>, OpError> {
+        Ok(self.wait_readable()?.drain_all())
+    }
+
+    // Пакетное блокирующее получение: ждет хотя бы один элемент и забирает
+    // до max под одной блокировкой (одно пробуждение на пакет). При
+    // max == 0 сразу пустой результат; закрытый и пустой буфер —
+    // OpError::Closed.
+    fn recv_many_blocking(&self, max: usize) -> Result<Vec<u8>, OpError> {
+        if max == 0 {
+            return Ok(Vec::new());
+        }
+        let mut buffer = self.wait_readable()?;
+        let count = buffer.len().min(max);
+        Ok((0..count).filter_map(|_| buffer.pop()).collect())
+    }
+
+    // Блокирующий просмотр головы с последующим решением: commit извлекает
+    // ее, release (или drop) оставляет другим потребителям. Гард держит
+    // блокировку буфера, поэтому просмотренное значение не может уйти
+    // к другому потоку до решения; трогать буфер, пока гард жив, нельзя.
+    // OpError::Closed — буфер закрыт и пуст (как у pop_blocking).
+    fn peek_blocking(&self) -> Result<PeekGuard<'_>, OpError> {
+        let buffer = self.wait_readable()?;
+        let value = buffer.peek().expect("буфер непуст");
+        Ok(PeekGuard { buffer, value })
+    }
+
+    // Запись с ожиданием свободного места. После close возвращает
+    // OpError::Closed, значение при этом не записывается.
+    fn push_blocking(&self, value: u8) -> Result<(), OpError> {
+        self.validate(value)?;
+        if let Some(alternation) = &self.alternation {
+            alternation.pending_writers.fetch_add(1, Ordering::Relaxed);
+        }
+        let result = self.try_acquire().and_then(|mut buffer| {
+            // Иначе ждали бы места в буфере нулевой вместимости вечно
+            self.check_capacity(&buffer)?;
+            while (buffer.is_full() || self.writer_should_yield(&buffer)) && !self.is_closed() {
+                buffer = buffer.wait(&self.not_full)?;
+            }
+            if self.is_closed() {
+                return Err(OpError::Closed);
+            }
+            buffer.push(value)?;
+            Ok(buffer)
+        });
+        if let Some(alternation) = &self.alternation {
+            alternation.pending_writers.fetch_sub(1, Ordering::Relaxed);
+        }
+        result.map(|buffer| {
+            drop(buffer);
+            self.observe(value);
+        })
+    }
+
+    // Гибрид обратного давления и затирания: ждем свободного места не дольше
+    // grace, а если его так и не появилось — вытесняем старейший элемент
+    // (как push_overwrite). Ok(true) — пришлось вытеснять. Производитель
+    // не блокируется дольше grace, даже если потребитель встал.
+    // В режиме with_alternation очередь не уступает.
+    fn push_blocking_then_overwrite(&self, value: u8, grace: Duration) -> Result<bool, OpError> {
+        self.validate(value)?;
+        let deadline = Instant::now().checked_add(grace);
+        let mut buffer = self.try_acquire()?;
+        self.check_capacity(&buffer)?;
+        while buffer.is_full() && !self.is_closed() {
+            let timed_out;
+            (buffer, timed_out) = buffer.wait_until(&self.not_full, deadline)?;
+            if timed_out {
+                break;
+            }
+        }
+        if self.is_closed() {
+            return Err(OpError::Closed);
+        }
+        if buffer.is_full() {
+            // Все слоты зарезервированы (begin_push) — вытеснять нечего
+            if buffer.is_empty() {
+                return Err(OpError::Buffer(BufferError::Full {
+                    capacity: buffer.capacity,
+                }));
+            }
+            buffer.push_overwrite(value);
+            drop(buffer);
+            self.observe(value);
+            return Ok(true);
+        }
+        buffer.push(value)?;
+        drop(buffer);
+        self.observe(value);
+        Ok(false)
+    }
+
+    // Извлечение головы, только если она равна expected (атомарно).
+    // Иначе голова остается на месте: Unexpected { found } или Empty.
+    fn pop_expected(&self, expected: u8) -> Result<(), BufferError> {
+        let mut buffer = self.acquire();
+        match buffer.peek() {
+            None => Err(BufferError::Empty),
+            Some(found) if found != expected => Err(BufferError::Unexpected { found }),
+            Some(_) => {
+                buffer.pop();
+                Ok(())
+            }
+        }
+    }
+
+    // Извлечение только из непрерывного отрезка с начала очереди (без
+    // разрыва на границе массива), не больше out.len(). Возвращает число
+    // скопированных байт.
+    fn pop_contiguous(&self, out: &mut [u8]) -> usize {
+        let mut buffer = self.acquire();
+        let count = buffer.contiguous_front().len().min(out.len());
+        for slot in &mut out[..count] {
+            *slot = buffer.pop().expect("элемент в непрерывном отрезке");
+        }
+        count
+    }
+
+    // Пакетное извлечение до out.len() элементов под одной блокировкой
+    // (в отличие от pop_contiguous — через границу массива тоже).
+    // Возвращает число извлеченных байт.
+    fn pop_slice(&self, out: &mut [u8]) -> usize {
+        let mut buffer = self.acquire();
+        let count = buffer.len().min(out.len());
+        for slot in &mut out[..count] {
+            *slot = buffer.pop().expect("count не больше size");
+        }
+        count
+    }
+
+    // Извлечение ровно N элементов в массив на стеке ("все или ничего"):
+    // если элементов меньше N, None и ничего не извлекается
+    fn pop_array<const N: usize>(&self) -> Option<[u8; N]> {
+        let mut buffer = self.acquire();
+        if buffer.len() < N {
+            return None;
+        }
+        Some(std::array::from_fn(|_| {
+            buffer.pop().expect("проверено, что элементов не меньше N")
+        }))
+    }
+
+    // Вычитывает весь буфер кадрами по N байт в порядке FIFO; неполный
+    // остаток (меньше N байт) возвращается вторым элементом. Буфер пустеет.
+    // N = 0 отвергается при компиляции.
+    fn drain_frames<const N: usize>(&self) -> (Vec<[u8; N]>, Vec<u8>) {
+        const { assert!(N > 0, "drain_frames требует N > 0") };
+        let values = self.acquire().drain_all();
+        let (frames, rest) = values.as_chunks::<N>();
+        (frames.to_vec(), rest.to_vec())
+    }
+
+    // Вычитывает весь буфер и сжимает подряд идущие одинаковые байты в пары
+    // (значение, длина серии): [1, 1, 2, 3, 3, 3] -> [(1, 2), (2, 1), (3, 3)]
+    fn drain_runs(&self) -> Vec<(u8, usize)> {
+        let values = self.acquire().drain_all();
+        let mut runs: Vec<(u8, usize)> = Vec::new();
+        for value in values {
+            match runs.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => runs.push((value, 1)),
+            }
+        }
+        runs
+    }
+
+    // Извлечение головы с ее порядковым номером (номера растут с каждой
+    // записью и не повторяются — защита от ABA при сверке с head_seq)
+    fn pop_seq(&self) -> Option<(u64, u8)> {
+        self.acquire().pop_seq()
+    }
+
+    // Номер текущей головы без извлечения
+    fn head_seq(&self) -> Option<u64> {
+        self.acquire().head_seq()
+    }
+
+    // Доступ к голове по ссылке, без копирования наружу: f вызывается под
+    // блокировкой с Some(&голова) или None для пустого буфера.
+    // Метод рассчитан на обобщенный буфер с большими T; здесь элемент —
+    // u8, копия которого дешева, так что выигрыш лишь в том, что чтение
+    // головы и решение по ней атомарны.
+    // Как и в transaction, f не должна снова блокировать этот буфер.
+    fn with_head<R, F: FnOnce(Option<&u8>) -> R>(&self, f: F) -> R {
+        let buffer = self.acquire();
+        let head = if buffer.is_empty() {
+            None
+        } else {
+            buffer.data.get(buffer.head).as_ref()
+        };
+        f(head)
+    }
+
+    // Атомарная замена содержимого (двойная буферизация): под одной
+    // блокировкой забирает текущие элементы и записывает new по порядку.
+    // Если new не помещается (с учетом резерва), Full и буфер не меняется.
+    fn swap_contents(&self, new: Vec<u8>) -> Result<Vec<u8>, BufferError> {
+        for &value in &new {
+            self.validate(value)?;
+        }
+        let mut buffer = self.acquire();
+        if new.len() > buffer.capacity - buffer.reserved {
+            return Err(BufferError::Full {
+                capacity: buffer.capacity,
+            });
+        }
+        let old = buffer.drain_all();
+        buffer
+            .push_slice(&new)
+            .expect("буфер пуст, место проверено");
+        drop(buffer);
+        self.observe_all(&new);
+        Ok(old)
+    }
+
+    // Вставка в отсортированный по возрастанию буфер с сохранением порядка
+    fn insert_sorted(&self, value: u8) -> Result<(), BufferError> {
+        self.validate(value)?;
+        self.acquire().insert_sorted(value)?;
+        self.observe(value);
+        Ok(())
+    }
+
+    // Согласованный снимок содержимого вместе с номером версии, снятые под
+    // одной блокировкой. Одинаковый номер — одинаковое содержимое; новый
+    // номер означает, что буфер мог измениться.
+    fn snapshot_versioned(&self) -> (u64, Vec<u8>) {
+        let buffer = self.acquire();
+        (buffer.op_seq, buffer.iter().collect())
+    }
+
+    // Неизменяемый снимок всего буфера, общий для всех вызовов до следующего
+    // изменения: копия делается лениво при первом вызове, а любой изменяющий
+    // доступ (по op_seq) делает кэш устаревшим — следующий вызов сделает
+    // новую копию. Дешево, когда снимки частые, а изменения редкие.
+    fn cow_snapshot(&self) -> Arc<RingBuffer> {
+        let buffer = self.acquire();
+        let mut cow = self.cow.lock().expect("мьютекс снимка отравлен");
+        match &*cow {
+            Some((op_seq, snapshot)) if *op_seq == buffer.op_seq => Arc::clone(snapshot),
+            _ => {
+                let snapshot = Arc::new(buffer.clone());
+                *cow = Some((buffer.op_seq, Arc::clone(&snapshot)));
+                snapshot
+            }
+        }
+    }
+
+    // Копия логических позиций start..end (от головы) без извлечения, с
+    // учетом перехода через границу. None, если end > size или start > end.
+    fn peek_range(&self, start: usize, end: usize) -> Option<Vec<u8>> {
+        let buffer = self.acquire();
+        if start > end || end > buffer.len() {
+            return None;
+        }
+        (start..end).map(|i| buffer.get(i)).collect()
+    }
+
+    // Свертка с вычитыванием: под одной блокировкой извлекает все элементы
+    // по порядку FIFO, сворачивая их в аккумулятор, и оставляет буфер пустым
+    fn fold_drain<B, F: FnMut(B, u8) -> B>(&self, init: B, mut f: F) -> B {
+        let mut buffer = self.acquire();
+        let mut acc = init;
+        while let Some(value) = buffer.pop() {
+            acc = f(acc, value);
+        }
+        acc
+    }
+
+    // Курсор по снимку текущего содержимого (FIFO); сам буфер не меняется
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            snapshot: self.acquire().iter().collect(),
+            position: 0,
+        }
+    }
+
+    // Бесконечный обход снимка содержимого по кругу в порядке FIFO (для
+    // round-robin по одному и тому же набору). Снимок делается один раз:
+    // последующие изменения буфера в обходе не видны. Пустой буфер — пустой
+    // итератор.
+    fn cycle_snapshot(&self) -> impl Iterator<Item = u8> + use<> {
+        let snapshot: Vec<u8> = self.acquire().iter().collect();
+        snapshot.into_iter().cycle()
+    }
+
+    // Просмотр первых (до) n живых элементов по ссылке, без копирования:
+    // f получает их как два отрезка хранилища (см. RingBuffer::as_slices),
+    // второй непуст, только если эти n элементов переходят через границу
+    // массива. f работает под блокировкой и не должна обращаться к этому
+    // буферу — повторная блокировка приведет к взаимоблокировке.
+    // Просмотр без копирования нужен для крупных T; буфер пока хранит
+    // только u8, поэтому отрезки — &[Option<u8>], а выигрыш — в отсутствии
+    // промежуточного Vec.
+    fn with_front_n<R, F: FnOnce(&[Option<u8>], &[Option<u8>]) -> R>(&self, n: usize, f: F) -> R {
+        let buffer = self.acquire();
+        let (first, second) = buffer.as_slices();
+        let first = &first[..n.min(first.len())];
+        let second = &second[..(n - first.len()).min(second.len())];
+        f(first, second)
+    }
+
+    // Передача всего содержимого в f одним непрерывным срезом с очисткой
+    // буфера — все под одной блокировкой, так что другие потоки не видят
+    // промежуточного состояния. f не должна обращаться к этому буферу.
+    fn drain_with<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let mut buffer = self.acquire();
+        let values = buffer.drain_all();
+        f(&values)
+    }
+
+    // Извлечение до max элементов с вызовом f на каждый, без промежуточного
+    // Vec; возвращает, сколько обработано. Цена — f работает под блокировкой:
+    // производители ждут все это время, поэтому f должна быть быстрой и не
+    // должна обращаться к этому буферу.
+    fn drain_n_with<F: FnMut(u8)>(&self, max: usize, mut f: F) -> usize {
+        let mut buffer = self.acquire();
+        let mut count = 0;
+        while count < max {
+            let Some(value) = buffer.pop() else {
+                break;
+            };
+            f(value);
+            count += 1;
+        }
+        count
+    }
+
+    // Сигнал обратного давления для производителя: 0.0 — пуст, 1.0 — полон
+    // (резерв считается занятым), линейно между ними. Буфер нулевой
+    // вместимости всегда полон.
+    fn backpressure(&self) -> f64 {
+        let buffer = self.acquire();
+        if buffer.capacity == 0 {
+            return 1.0;
+        }
+        (buffer.size + buffer.reserved) as f64 / buffer.capacity as f64
+    }
+
+    // Пора ли притормозить: давление достигло threshold
+    fn should_throttle(&self, threshold: f64) -> bool {
+        self.backpressure() >= threshold
+    }
+
+    // Заполнение повторяющимся шаблоном: прежнее содержимое затирается
+    // (как в overwrite_with), затем pattern пишется по кругу до заполнения.
+    // Пустой шаблон — ничего не делает.
+    fn fill_pattern(&self, pattern: &[u8]) -> Result<(), BufferError> {
+        self.validate_all(pattern)?;
+        if pattern.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = self.acquire();
+        let room = buffer.capacity - buffer.reserved;
+        let data: Vec<u8> = pattern.iter().copied().cycle().take(room).collect();
+        buffer.overwrite_with(&data);
+        drop(buffer);
+        self.observe_all(&data);
+        Ok(())
+    }
+
+    // Отделение последних min(n, size) элементов в новый буфер вместимости n.
+    // Снимаются с хвоста, но в новом буфере идут в прежнем порядке FIFO;
+    // более ранние элементы остаются здесь.
+    fn split_off_back(&self, n: usize) -> RingBuffer {
+        let mut buffer = self.acquire();
+        let count = n.min(buffer.len());
+        let mut tail: Vec<u8> = (0..count).filter_map(|_| buffer.pop_back()).collect();
+        tail.reverse();
+        RingBuffer::with_capacity_from_iter(n, tail).expect("count не больше n")
+    }
+
+    // Пакетная запись с затиранием: пишется весь срез, самые старые
+    // элементы вытесняются по мере надобности (при values.len() >= capacity
+    // остаются только последние capacity байт среза). Возвращает
+    // вытесненное в порядке вытеснения.
+    fn push_slice_overwrite(&self, values: &[u8]) -> Result<Vec<u8>, BufferError> {
+        self.validate_all(values)?;
+        let mut buffer = self.acquire();
+        // Все слоты зарезервированы: каждое значение возвращается как есть
+        let stored = !(buffer.is_full() && buffer.is_empty());
+        let evicted = values
+            .iter()
+            .filter_map(|&value| buffer.push_overwrite(value))
+            .collect();
+        drop(buffer);
+        if stored {
+            self.observe_all(values);
+        }
+        Ok(evicted)
+    }
+
+    // Запись с вытеснением до evict старых элементов при переполнении
+    fn push_evict_n(&self, value: u8, evict: usize) -> Result<Vec<u8>, BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        let stored = !(buffer.is_full() && buffer.is_empty());
+        let evicted = buffer.push_evict_n(value, evict);
+        drop(buffer);
+        if stored {
+            self.observe(value);
+        }
+        Ok(evicted)
+    }
+
+    // Текущее поколение содержимого
+    fn generation(&self) -> u64 {
+        self.acquire().generation
+    }
+
+    // Оптимистичная очистка: только если поколение все еще expected.
+    // Тогда буфер очищается, поколение увеличивается, и результат true;
+    // иначе (поколение устарело) ничего не меняется.
+    fn clear_if_generation(&self, expected: u64) -> bool {
+        let mut buffer = self.acquire();
+        if buffer.generation != expected {
+            return false;
+        }
+        buffer.clear();
+        buffer.generation += 1;
+        true
+    }
+
+    // Сброс по заполнению: под одной блокировкой забирает все элементы,
+    // только если буфер полон (is_full), иначе None и буфер не меняется.
+    // Без гонки между отдельными is_full и drain_all.
+    fn drain_if_full(&self) -> Option<Vec<u8>> {
+        let mut buffer = self.acquire();
+        buffer.is_full().then(|| buffer.drain_all())
+    }
+
+    // Вычитывание всего буфера, только если в нем ровно expected_len
+    // элементов. Иначе Mismatch, и буфер не меняется: так ловится
+    // расхождение в счете между производителем и потребителем.
+    fn drain_expect(&self, expected_len: usize) -> Result<Vec<u8>, BufferError> {
+        let mut buffer = self.acquire();
+        if buffer.size != expected_len {
+            return Err(BufferError::Mismatch {
+                expected: expected_len,
+                actual: buffer.size,
+            });
+        }
+        Ok(buffer.drain_all())
+    }
+
+    // Тестовый помощник: вычитывает весь буфер и паникует, если порядок не
+    // совпал с expected. В сообщении — обе последовательности и первая
+    // расходящаяся позиция.
+    #[cfg(any(test, feature = "test-util"))]
+    #[track_caller]
+    fn assert_drains_to(&self, expected: &[u8]) {
+        let actual = self.acquire().drain_all();
+        if actual == expected {
+            return;
+        }
+        let first_diff = actual
+            .iter()
+            .zip(expected)
+            .position(|(a, e)| a != e)
+            .unwrap_or(actual.len().min(expected.len()));
+        panic!(
+            "SafeRingBuffer drained in unexpected order\n  expected: {expected:?}\n    actual: {actual:?}\n  first difference at index {first_diff}"
+        );
+    }
+
+    // Извлечение всех подходящих под f элементов с любой позиции (в отличие
+    // от извлечения с головы); остальные остаются в прежнем порядке
+    fn extract_if<F: FnMut(&u8) -> bool>(&self, f: F) -> Vec<u8> {
+        self.acquire().extract_if(f)
+    }
+
+    // Оставляет только элементы, для которых f истинна (порядок сохраняется),
+    // и возвращает, сколько удалено
+    fn retain_count<F: FnMut(&u8) -> bool>(&self, mut f: F) -> usize {
+        self.acquire().extract_if(|value| !f(value)).len()
+    }
+
+    // Удаление первого (в порядке FIFO) элемента, равного value, с любой
+    // позиции; остальные сохраняют порядок. Удаление головы равносильно pop.
+    fn remove_first(&self, value: u8) -> bool {
+        let mut found = false;
+        let removed = self.acquire().extract_if(|&existing| {
+            let matches = !found && existing == value;
+            found |= matches;
+            matches
+        });
+        !removed.is_empty()
+    }
+
+    // Добавление, сообщающее об отравленном мьютексе (и о закрытом буфере)
+    // ошибкой вместо паники
+    fn try_push(&self, value: u8) -> Result<(), OpError> {
+        self.validate(value)?;
+        let mut buffer = self.try_acquire()?;
+        if self.is_closed() {
+            return Err(OpError::Closed);
+        }
+        self.check_capacity(&buffer)?;
+        buffer.push(value)?;
+        drop(buffer);
+        self.observe(value);
+        Ok(())
+    }
+
+    // Извлечение, сообщающее об отравленном мьютексе ошибкой вместо паники;
+    // пустой буфер — BufferError::Empty
+    fn try_pop(&self) -> Result<u8, OpError> {
+        let mut buffer = self.try_acquire()?;
+        buffer.pop().ok_or(OpError::Buffer(BufferError::Empty))
+    }
+
+    // Пакетное добавление под одной блокировкой: либо весь срез, либо ничего
+    fn push_slice(&self, values: &[u8]) -> Result<(), BufferError> {
+        for &value in values {
+            self.validate(value)?;
+        }
+        let mut buffer = self.acquire();
+        self.check_capacity(&buffer)?;
+        buffer.push_slice(values)?;
+        drop(buffer);
+        self.observe_all(values);
+        Ok(())
+    }
+
+    // Запись без дубликатов по ключу: если у какого-то живого элемента
+    // key_of совпадает с ключом value, запись отклоняется (Ok(false)).
+    // Например, старшая тетрада как идентификатор: key_of = |v| v >> 4.
+    // Проверка и запись идут под одной блокировкой.
+    // Ключ задумывался как поле структуры в RingBuffer<T>; пока элементы —
+    // только u8, ключом служит производная от байта (маска, сдвиг и т.п.).
+    fn push_unique_by<K: PartialEq, F: Fn(&u8) -> K>(
+        &self,
+        value: u8,
+        key_of: F,
+    ) -> Result<bool, BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        self.check_capacity(&buffer)?;
+        let key = key_of(&value);
+        if buffer.iter().any(|existing| key_of(&existing) == key) {
+            return Ok(false);
+        }
+        buffer.push(value)?;
+        drop(buffer);
+        self.observe(value);
+        Ok(true)
+    }
+
+    // Пакетное добавление "сколько влезет" под одной блокировкой: запись идет
+    // до первого значения, которое не поместилось или отклонено валидатором.
+    // В отличие от push_slice, принятая часть остается в буфере.
+    fn push_slice_reporting(&self, values: &[u8]) -> Result<(), PartialPush> {
+        // Валидатор вызывается до захвата блокировки, как и в push_slice
+        let valid = values
+            .iter()
+            .position(|&value| self.validate(value).is_err())
+            .unwrap_or(values.len());
+        let mut buffer = self.acquire();
+        if valid > 0 && self.check_capacity(&buffer).is_err() {
+            return Err(PartialPush { accepted: 0 });
+        }
+        let accepted = values[..valid]
+            .iter()
+            .take_while(|&&value| buffer.push(value).is_ok())
+            .count();
+        drop(buffer);
+        self.observe_all(&values[..accepted]);
+        if accepted < values.len() {
+            return Err(PartialPush { accepted });
+        }
+        Ok(())
+    }
+
+    // Добавление с повторами: при переполнении ждем base_delay * 2^попытка
+    // и пробуем снова, всего не более max_attempts попыток (минимум одна).
+    // Если все попытки неудачны, возвращается последняя ошибка.
+    fn push_with_retry(
+        &self,
+        value: u8,
+        max_attempts: usize,
+        base_delay: Duration,
+    ) -> Result<(), BufferError> {
+        let mut attempt = 0;
+        loop {
+            match self.push(value) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt + 1 >= max_attempts => return Err(e),
+                Err(_) => {
+                    let factor = 1u32 << attempt.min(31);
+                    thread::sleep(base_delay.saturating_mul(factor));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    // Первая фаза двухфазной записи: резервирует слот (или Full), но значение
+    // не видно для pop до PushTicket::commit. rollback или drop без commit
+    // освобождает резерв.
+    fn begin_push(&self, value: u8) -> Result<PushTicket<'_>, BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        self.check_capacity(&buffer)?;
+        if buffer.is_full() {
+            return Err(BufferError::Full {
+                capacity: buffer.capacity,
+            });
+        }
+        buffer.reserved += 1;
+        Ok(PushTicket {
+            buffer: self,
+            value,
+            finished: false,
+        })
+    }
+
+    // Добавление "по возможности": при переполнении новый элемент просто
+    // отбрасывается (в отличие от push_overwrite, который вытесняет старый)
+    fn push_best_effort(&self, value: u8) -> bool {
+        self.push(value).is_ok()
+    }
+
+    // Потокобезопасное добавление в начало
+    fn push_front(&self, value: u8) -> Result<(), BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        self.check_capacity(&buffer)?;
+        buffer.push_front(value)?;
+        drop(buffer);
+        self.observe(value);
+        Ok(())
+    }
+
+    // Потокобезопасное извлечение с конца
+    fn pop_back(&self) -> Option<u8> {
+        let mut buffer = self.acquire();
+        buffer.pop_back()
+    }
+
+    // Потокобезопасное добавление с затиранием старейшего элемента
+    fn push_overwrite(&self, value: u8) -> Result<Option<u8>, BufferError> {
+        self.validate(value)?;
+        let mut buffer = self.acquire();
+        let stored = !(buffer.is_full() && buffer.is_empty());
+        let evicted = buffer.push_overwrite(value);
+        drop(buffer);
+        if stored {
+            self.observe(value);
+        }
+        Ok(evicted)
+    }
+
+    // Потокобезопасная замена содержимого "последним окном" данных
+    fn overwrite_with(&self, data: &[u8]) -> Result<(), BufferError> {
+        self.validate_all(data)?;
+        let mut buffer = self.acquire();
+        buffer.overwrite_with(data);
+        // Записан только хвост data, уместившийся в буфер
+        let written = &data[data.len() - buffer.len()..];
+        drop(buffer);
+        self.observe_all(written);
+        Ok(())
+    }
+
+    // Совпадает ли содержимое (в порядке FIFO) с expected; буфер не меняется
+    fn contents_eq(&self, expected: &[u8]) -> bool {
+        let buffer = self.acquire();
+        buffer.len() == expected.len() && buffer.iter().eq(expected.iter().copied())
+    }
+
+    // Отпечаток содержимого под блокировкой
+    fn fingerprint(&self) -> u64 {
+        self.acquire().fingerprint()
+    }
+
+    // Сумма текущего содержимого
+    fn window_sum(&self) -> u64 {
+        self.acquire().window_sum()
+    }
+
+    // Скользящее среднее по текущему содержимому
+    fn window_avg(&self) -> f64 {
+        self.acquire().window_avg()
+    }
+
+    // Изменение вместимости с сохранением содержимого
+    fn resize(&self, new_capacity: usize) -> Result<(), BufferError> {
+        self.acquire().resize(new_capacity)
+    }
+
+    // Освобождение лишней памяти хранилища (см. RingBuffer::shrink_to_fit)
+    fn shrink_to_fit(&self) {
+        self.acquire().shrink_to_fit()
+    }
+
+    // Уменьшение вместимости недогруженного буфера с сохранением содержимого
+    fn maybe_shrink(&self) -> bool {
+        self.acquire().maybe_shrink()
+    }
+
+    // Средняя скорость записи (элементов в секунду) за последние
+    // PUSH_RATE_WINDOW_SECS секунд
+    fn push_rate_per_sec(&self) -> f64 {
+        self.acquire().push_rate.rate_per_sec(Instant::now())
+    }
+
+    // Примерный объем памяти буфера (см. RingBuffer::memory_usage)
+    fn memory_usage(&self) -> usize {
+        self.acquire().memory_usage()
+    }
+
+    // История изменений вместимости (resize, maybe_shrink) от старых к новым:
+    // (момент, старая вместимость, новая). Хранятся только последние
+    // CAPACITY_HISTORY_LEN записей.
+    fn capacity_history(&self) -> Vec<(Instant, usize, usize)> {
+        self.acquire().capacity_history.iter().copied().collect()
+    }
+
+    // Метрики в текстовом формате Prometheus. Все значения снимаются
+    // под одной блокировкой, поэтому согласованы между собой.
+    fn prometheus_metrics(&self, prefix: &str) -> String {
+        let buffer = self.acquire();
+        let metrics = [
+            ("size", "gauge", buffer.size as u64),
+            ("capacity", "gauge", buffer.capacity as u64),
+            ("pushed_total", "counter", buffer.pushed),
+            ("popped_total", "counter", buffer.popped),
+            ("overwritten_total", "counter", buffer.overwritten),
+        ];
+
+        // У безымянного буфера метка опускается
+        let labels = if self.label.is_empty() {
+            String::new()
+        } else {
+            format!("{{buffer=\"{}\"}}", self.label)
+        };
+
+        let mut out = String::new();
+        for (name, kind, value) in metrics {
+            out.push_str(&format!("# TYPE {prefix}_{name} {kind}\n"));
+            out.push_str(&format!("{prefix}_{name}{labels} {value}\n"));
+        }
+        out
+    }
+
+    // Отчет о внутреннем состоянии под блокировкой (см. RingBuffer::debug_dump)
+    fn debug_dump(&self) -> String {
+        self.acquire().debug_dump()
+    }
+
+    // Транзакция: одна блокировка на всю операцию "посмотреть, решить, извлечь".
+    // Важно: f не должна снова обращаться к этому же буферу через SafeRingBuffer —
+    // мьютекс уже захвачен, повторная блокировка приведет к взаимоблокировке.
+    fn transaction<R>(&self, f: impl FnOnce(&mut RingBuffer) -> R) -> R {
+        let mut buffer = self.acquire();
+        f(&mut buffer)
+    }
+}
+
+// Рассылка одного значения в несколько буферов по принципу "все или ничего".
+// Блокировки берутся в порядке адресов (без повторов), поэтому параллельные
+// рассылки по пересекающимся наборам буферов не взаимоблокируются.
+// Если хоть один буфер полон, значение не попадает никуда.
+fn broadcast(value: u8, buffers: &[&SafeRingBuffer]) -> Result<(), BufferError> {
+    let mut ordered: Vec<&SafeRingBuffer> = buffers.to_vec();
+    ordered.sort_by_key(|buffer| *buffer as *const SafeRingBuffer);
+    ordered.dedup_by_key(|buffer| *buffer as *const SafeRingBuffer);
+    for buffer in &ordered {
+        buffer.validate(value)?;
+    }
+
+    let mut guards: Vec<LockGuard<'_>> = ordered.iter().map(|b| b.acquire()).collect();
+    if let Some(full) = guards.iter().find(|guard| guard.is_full()) {
+        return Err(BufferError::Full {
+            capacity: full.capacity,
+        });
+    }
+    for guard in &mut guards {
+        guard.push(value).expect("место проверено под блокировкой");
+    }
+    drop(guards);
+    for buffer in &ordered {
+        buffer.observe(value);
+    }
+    Ok(())
+}
+
+// Слияние нескольких буферов в один отсортированный по возрастанию Vec.
+// Предполагается, что каждый буфер уже отсортирован по возрастанию от головы
+// к хвосту; иначе результат не обязан быть отсортированным.
+// Буферы блокируются по одному и только на время peek/pop, так что
+// одновременно удерживается не больше одной блокировки.
+fn drain_merge_sorted(buffers: &[&SafeRingBuffer]) -> Vec<u8> {
+    let mut merged = Vec::new();
+    loop {
+        let smallest = buffers
+            .iter()
+            .filter_map(|buffer| buffer.acquire().peek().map(|head| (head, *buffer)))
+            .min_by_key(|(head, _)| *head);
+        let Some((_, buffer)) = smallest else {
+            break;
+        };
+        // Голова могла смениться между peek и pop, если буфер читает
+        // кто-то еще — берем то, что действительно извлекли
+        if let Some(value) = buffer.pop() {
+            merged.push(value);
+        }
+    }
+    merged
+}
+
+// Зарезервированная запись (см. SafeRingBuffer::begin_push)
+struct PushTicket<'a> {
+    buffer: &'a SafeRingBuffer,
+    value: u8,
+    finished: bool, // commit или rollback уже выполнены
+}
+
+impl PushTicket<'_> {
+    // Делает значение видимым: записывает его в хвост на место резерва и
+    // учитывает запись, как push (with_sampler, with_reservoir). После close
+    // — Err(Closed), отравленный мьютекс — Err(Poisoned); в обоих случаях
+    // резерв снимается в Drop, а значение не записывается.
+    fn commit(mut self) -> Result<(), OpError> {
+        if self.buffer.is_closed() {
+            return Err(OpError::Closed);
+        }
+        let mut buffer = self.buffer.try_acquire()?;
+        buffer.reserved -= 1;
+        let value = self.value;
+        // Safety: зарезервированный слот гарантирует свободное место
+        unsafe { buffer.push_unchecked(value) };
+        self.finished = true;
+        drop(buffer);
+        self.buffer.observe(value);
+        Ok(())
+    }
+
+    // Отказ от записи: буфер остается без изменений
+    fn rollback(self) {
+        // Резерв снимается в Drop
+    }
+}
+
+// Сколько раз release_reserved пробует захватить занятый мьютекс
+const RELEASE_ATTEMPTS: usize = 64;
+
+impl Drop for PushTicket<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.buffer.release_reserved(1);
+        }
+    }
+}
+
+impl SafeRingBuffer {
+    // Отпускает count зарезервированных слотов из drop (PushTicket,
+    // RingBufMut). Резерв отпускается через счетчик, а снимает его тот, кто
+    // держит мьютекс: так drop не паникует и не блокируется ни на отравленном
+    // мьютексе, ни внутри transaction этого же потока.
+    fn release_reserved(&self, count: usize) {
+        self.released_reservations
+            .fetch_add(count, Ordering::Relaxed);
+        for _ in 0..RELEASE_ATTEMPTS {
+            let mut guard = match self.inner.try_lock() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                // Занято: резерв снимет владелец при освобождении, если еще
+                // не прошел этот шаг, — повторяем, пока счетчик не обнулится
+                Err(TryLockError::WouldBlock) => {
+                    if self.released_reservations.load(Ordering::Relaxed) == 0 {
+                        return;
+                    }
+                    thread::yield_now();
+                    continue;
+                }
+            };
+            if self.apply_released(&mut guard) {
+                drop(guard);
+                self.not_full.notify_all(); // Слот снова свободен
+            }
+            return;
+        }
+        // Мьютекс держит этот же поток (или держат долго): резерв снимет
+        // ближайшее освобождение или захват блокировки
+    }
+}
+
+// Просмотренная голова буфера (см. SafeRingBuffer::peek_blocking)
+struct PeekGuard<'a> {
+    buffer: LockGuard<'a>,
+    value: u8,
+}
+
+impl PeekGuard<'_> {
+    fn value(&self) -> u8 {
+        self.value
+    }
+
+    // Забираем просмотренный элемент
+    fn commit(mut self) -> u8 {
+        self.buffer
+            .pop()
+            .expect("голова удерживается под блокировкой")
+    }
+
+    // Оставляем элемент в буфере; то же, что просто отпустить гард
+    fn release(self) {}
+}
+
+// Курсор для неразрушающего просмотра с возвратом (см. SafeRingBuffer::cursor).
+// Работает по снимку: изменения буфера после создания курсора не видны.
+#[derive(Debug, Clone)]
+struct Cursor {
+    snapshot: Vec<u8>,
+    position: usize, // Индекс следующего элемента снимка
+}
+
+impl Cursor {
+    // Следующий элемент без продвижения
+    fn peek(&self) -> Option<u8> {
+        self.snapshot.get(self.position).copied()
+    }
+
+    // Возврат к началу снимка
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    // Сколько элементов уже пройдено
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let value = self.peek()?;
+        self.position += 1;
+        Some(value)
+    }
+}
+
+impl fmt::Display for SafeRingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buffer = self.acquire();
+        if self.label.is_empty() {
+            write!(f, "SafeRingBuffer({}/{})", buffer.size, buffer.capacity)
+        } else {
+            write!(
+                f,
+                "SafeRingBuffer({}, {}/{})",
+                self.label, buffer.size, buffer.capacity
+            )
+        }
+    }
+}
+
+fn main() {
+    // Создаем потокобезопасный буфер на 5 элементов
     let buffer = Arc::new(SafeRingBuffer::new(5));
 
-    // Демонстрация работы в одном потоке
-    println!("=== Однопоточная демонстрация ===");
-    buffer.push(10).unwrap();
-    buffer.push(20).unwrap();
-    println!("Извлекли: {:?}", buffer.pop()); // 10
-    buffer.push(30).unwrap();
-    println!("Извлекли: {:?}", buffer.pop()); // 20
-    println!("Извлекли: {:?}", buffer.pop()); // 30
+    // Демонстрация работы в одном потоке
+    println!("=== Однопоточная демонстрация ===");
+    buffer.push(10).unwrap();
+    buffer.push(20).unwrap();
+    println!("Извлекли: {:?}", buffer.pop()); // 10
+    buffer.push(30).unwrap();
+    println!("Извлекли: {:?}", buffer.pop()); // 20
+    println!("Извлекли: {:?}", buffer.pop()); // 30
+
+    // Многопоточная демонстрация
+    println!("\n=== Многопоточная демонстрация ===");
+    let buffer_clone = Arc::clone(&buffer);
+
+    // Поток-писатель
+    let writer = thread::spawn(move || {
+        for i in 1..=5 {
+            buffer_clone.push(i).unwrap();
+            println!("Писатель записал: {}", i);
+        }
+    });
+
+    // Поток-читатель
+    let reader = thread::spawn(move || {
+        for _ in 1..=5 {
+            if let Some(val) = buffer.pop() {
+                println!("Читатель прочитал: {}", val);
+            }
+        }
+    });
+
+    // Ждем завершения потоков
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
+
+// Тесты
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_thread() {
+        let buffer = SafeRingBuffer::new(3);
+
+        // Заполняем буфер
+        assert_eq!(buffer.push(1), Ok(()));
+        assert_eq!(buffer.push(2), Ok(()));
+        assert_eq!(buffer.push(3), Ok(()));
+        assert_eq!(buffer.push(4), Err(BufferError::Full { capacity: 3 })); // Переполнение
+
+        // Читаем данные
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.push(4), Ok(())); // Теперь можно записать
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), Some(4));
+        assert_eq!(buffer.pop(), None); // Буфер пуст
+    }
+
+    #[test]
+    fn test_multi_thread() {
+        let buffer = Arc::new(SafeRingBuffer::new(100));
+        let mut handles = vec![];
+
+        // Запускаем 5 писателей
+        for i in 0..5 {
+            let buffer = Arc::clone(&buffer);
+            handles.push(thread::spawn(move || {
+                for j in 1..=10 {
+                    if let Err(e) = buffer.push(i * 20 + j) {
+                        println!("Ошибка записи: {:?}", e);
+                        break;
+                    }
+                }
+            }));
+        }
+
+        // Ждем завершения писателей
+        for handle in handles.drain(..) {
+            handle.join().unwrap();
+        }
+
+        // Запускаем 5 читателей
+        let results = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..5 {
+            let buffer = Arc::clone(&buffer);
+            let results = Arc::clone(&results);
+            handles.push(thread::spawn(move || {
+                for _ in 1..=10 {
+                    if let Some(val) = buffer.pop() {
+                        results.lock().unwrap().push(val);
+                    }
+                }
+            }));
+        }
+
+        // Ждем завершения читателей
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Проверяем, что все данные прочитаны
+        let results = results.lock().unwrap();
+        assert_eq!(results.len(), 50);
+    }
+
+    #[test]
+    fn test_transaction() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
+        buffer.push(10).unwrap();
+
+        // Извлекаем пару, только если сумма первых двух элементов больше порога
+        let pop_pair_if = |threshold: u16| {
+            buffer.transaction(|buf| {
+                let (a, b) = (buf.get(0)?, buf.get(1)?);
+                if a as u16 + b as u16 > threshold {
+                    Some((buf.pop()?, buf.pop()?))
+                } else {
+                    None
+                }
+            })
+        };
+
+        assert_eq!(pop_pair_if(7), None); // 3 + 4 = 7, не больше порога
+        assert_eq!(buffer.transaction(|buf| buf.len()), 3); // Ничего не извлечено
+        assert_eq!(pop_pair_if(6), Some((3, 4)));
+        assert_eq!(buffer.transaction(|buf| buf.peek()), Some(10));
+        assert_eq!(pop_pair_if(0), None); // Остался один элемент
+    }
+
+    #[test]
+    fn test_push_overwrite() {
+        let buffer = SafeRingBuffer::new(2);
+        assert_eq!(buffer.push_overwrite(1), Ok(None));
+        assert_eq!(buffer.push_overwrite(2), Ok(None));
+        assert_eq!(buffer.push_overwrite(3), Ok(Some(1))); // Вытеснили самый старый
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap();
+        buffer.push_overwrite(4).unwrap(); // Затирает 1
+        buffer.pop();
+
+        let text = buffer.prometheus_metrics("ring");
+        for line in [
+            "# TYPE ring_size gauge",
+            "ring_size 2",
+            "# TYPE ring_capacity gauge",
+            "ring_capacity 3",
+            "# TYPE ring_pushed_total counter",
+            "ring_pushed_total 4",
+            "# TYPE ring_popped_total counter",
+            "ring_popped_total 1",
+            "# TYPE ring_overwritten_total counter",
+            "ring_overwritten_total 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "нет строки {line:?} в\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pop_back() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap();
+        assert_eq!(buffer.pop_back(), Some(3));
+        assert_eq!(buffer.pop_back(), Some(2));
+        assert_eq!(buffer.pop_back(), Some(1));
+        assert_eq!(buffer.pop_back(), None);
+    }
+
+    #[test]
+    fn test_pop_back_as_deque() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap(); // tail снова 0
+        assert_eq!(buffer.pop(), Some(1));
+        buffer.push(4).unwrap(); // Запись в слот 0, tail = 1
+
+        assert_eq!(buffer.pop_back(), Some(4)); // Откатываемся через 1 -> 0
+        assert_eq!(buffer.pop_back(), Some(3)); // Переход через границу 0 -> 2
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_push_front() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap();
+        assert_eq!(buffer.push_front(1), Ok(())); // head == 0, переход на конец
+        assert_eq!(buffer.push_front(0), Err(BufferError::Full { capacity: 3 }));
+
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_push_front_wraps() {
+        let mut buffer = RingBuffer::new(3);
+        assert_eq!(buffer.push_front(2), Ok(()));
+        assert_eq!(buffer.head, 2); // Записали в последний слот
+        assert_eq!(buffer.push_front(1), Ok(()));
+        buffer.push(3).unwrap();
+
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+    }
+
+    // Простой детерминированный генератор (xorshift64) для воспроизводимых тестов
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    // Извлекает все элементы в порядке FIFO
+    fn drain(buffer: &SafeRingBuffer) -> Vec<u8> {
+        std::iter::from_fn(|| buffer.pop()).collect()
+    }
+
+    #[test]
+    fn test_overwrite_with() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.push(42).unwrap();
+
+        // Срез длиннее вместимости: остаются последние 5 байт
+        buffer
+            .overwrite_with(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10])
+            .unwrap();
+        assert_eq!(drain(&buffer), vec![6, 7, 8, 9, 10]);
+
+        // Короткий срез занимает буфер целиком, без старых данных
+        buffer.push(42).unwrap();
+        buffer.overwrite_with(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.transaction(|buf| buf.len()), 3);
+        assert_eq!(drain(&buffer), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_contention_count_single_thread() {
+        let buffer = SafeRingBuffer::new(4);
+        for i in 0..100 {
+            buffer.push_overwrite(i).unwrap();
+            buffer.pop();
+        }
+        assert_eq!(buffer.contention_count(), 0);
+    }
+
+    #[test]
+    fn test_contention_count_multi_thread() {
+        let buffer = Arc::new(SafeRingBuffer::new(16));
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+
+        // Один поток заведомо держит блокировку, остальные в нее упираются
+        let holder = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                buffer.transaction(|_| {
+                    locked_tx.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                })
+            })
+        };
+        locked_rx.recv().unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || buffer.push(i).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        holder.join().unwrap();
+        assert!(buffer.contention_count() > 0);
+    }
+
+    #[test]
+    fn test_push_best_effort() {
+        let buffer = SafeRingBuffer::new(2);
+        assert!(buffer.push_best_effort(1));
+        assert!(buffer.push_best_effort(2));
+        assert!(!buffer.push_best_effort(3)); // Полон, 3 отброшено
+        assert_eq!(drain(&buffer), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_window_sum() {
+        let mut buffer = RingBuffer::new(4);
+        let check = |buffer: &RingBuffer| {
+            let fold: u64 = buffer.iter().map(|v| v as u64).sum();
+            assert_eq!(buffer.window_sum(), fold);
+        };
+
+        assert_eq!(buffer.window_avg(), 0.0);
+        for value in [200, 100, 50] {
+            buffer.push(value).unwrap();
+            check(&buffer);
+        }
+        buffer.pop();
+        check(&buffer);
+        buffer.push_overwrite(255);
+        buffer.push_overwrite(255); // Вытесняет 100
+        check(&buffer);
+        buffer.pop_back();
+        buffer.push_front(1).unwrap();
+        check(&buffer);
+        buffer.overwrite_with(&[10, 20, 30]);
+        check(&buffer);
+        assert_eq!(buffer.window_sum(), 60);
+        assert_eq!(buffer.window_avg(), 20.0);
+    }
+
+    #[test]
+    fn test_share_and_deep_copy() {
+        let original = Arc::new(SafeRingBuffer::new(4));
+        original.push(1).unwrap();
+        original.push(2).unwrap();
+
+        let shared = original.share();
+        let copy = original.deep_copy();
+        original.push(3).unwrap();
+
+        // Копия содержит состояние на момент копирования
+        assert_eq!(drain(&copy), vec![1, 2]);
+        // Разделяемая ссылка видит все изменения оригинала
+        assert_eq!(drain(&shared), vec![1, 2, 3]);
+        assert_eq!(original.pop(), None);
+    }
+
+    #[test]
+    fn test_named_buffer_label() {
+        let named = SafeRingBuffer::named(4, "ingest");
+        named.push(7).unwrap();
+        let text = named.prometheus_metrics("ring");
+        assert!(text.contains("ring_size{buffer=\"ingest\"} 1\n"));
+        assert!(text.contains("ring_capacity{buffer=\"ingest\"} 4\n"));
+        assert_eq!(named.to_string(), "SafeRingBuffer(ingest, 1/4)");
+
+        let unnamed = SafeRingBuffer::new(4);
+        let text = unnamed.prometheus_metrics("ring");
+        assert!(text.contains("ring_size 0\n"));
+        assert!(!text.contains("buffer="));
+        assert_eq!(unnamed.to_string(), "SafeRingBuffer(0/4)");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "reentrant lock on SafeRingBuffer")]
+    fn test_reentrant_lock_panics() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.transaction(|_| buffer.push(1)).unwrap();
+    }
+
+    #[test]
+    fn test_lock_released_after_transaction() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.transaction(|buf| buf.push(1)).unwrap();
+        buffer.push(2).unwrap(); // Тот же поток после освобождения
+
+        // Другой поток захватывает блокировку как обычно
+        thread::scope(|s| {
+            s.spawn(|| buffer.push(3).unwrap());
+        });
+        assert_eq!(drain(&buffer), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_maybe_shrink() {
+        let buffer = SafeRingBuffer::new(16);
+        for i in 0..16 {
+            buffer.push(i).unwrap();
+        }
+        assert!(!buffer.maybe_shrink()); // Полный буфер не уменьшается
+
+        for _ in 0..13 {
+            buffer.pop();
+        }
+        assert!(buffer.maybe_shrink());
+        assert_eq!(buffer.transaction(|buf| buf.capacity), 8);
+        assert!(!buffer.maybe_shrink()); // 3 из 8 — уже не меньше четверти
+
+        buffer.push(16).unwrap();
+        assert_eq!(drain(&buffer), vec![13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn test_push_slice() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.push(0).unwrap();
+        buffer.pop(); // Сдвигаем head, чтобы срез перешел через границу
+
+        assert_eq!(buffer.push_slice(&[1, 2, 3, 4]), Ok(()));
+        assert_eq!(
+            buffer.push_slice(&[5, 6]),
+            Err(BufferError::Full { capacity: 5 })
+        );
+        assert_eq!(buffer.push_slice(&[5]), Ok(()));
+        assert_eq!(buffer.push_slice(&[]), Ok(()));
+        assert_eq!(drain(&buffer), vec![1, 2, 3, 4, 5]);
+        assert_eq!(buffer.window_sum(), 0);
+    }
+
+    #[test]
+    fn test_vecdeque_round_trip() {
+        let deque: VecDeque<u8> = VecDeque::from(vec![1, 2, 3, 4]);
+        let mut buffer = RingBuffer::from(deque.clone());
+        assert_eq!(buffer.capacity, 4);
+        assert!(buffer.is_full());
+        assert_eq!(VecDeque::from(buffer.clone()), deque);
+
+        // Порядок сохраняется и для "провернутого" буфера
+        buffer.pop();
+        buffer.push(5).unwrap();
+        assert_eq!(VecDeque::from(buffer), VecDeque::from(vec![2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn test_push_with_retry() {
+        let delay = Duration::from_millis(20);
+
+        // Успех с первой попытки — без ожидания
+        let buffer = Arc::new(SafeRingBuffer::new(1));
+        let start = Instant::now();
+        assert_eq!(buffer.push_with_retry(1, 3, delay), Ok(()));
+        assert!(start.elapsed() < delay);
+
+        // Постоянно полный буфер: 3 попытки, паузы 20 + 40 мс
+        let start = Instant::now();
+        assert_eq!(
+            buffer.push_with_retry(2, 3, delay),
+            Err(BufferError::Full { capacity: 1 })
+        );
+        assert!(start.elapsed() >= delay * 3);
+
+        // Место освобождается конкурентным чтением во время ожидания
+        let reader = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(30));
+                buffer.pop()
+            })
+        };
+        assert_eq!(buffer.push_with_retry(3, 5, delay), Ok(()));
+        assert_eq!(reader.join().unwrap(), Some(1));
+        assert_eq!(buffer.pop(), Some(3));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut a = RingBuffer::new(4);
+        a.push_slice(&[1, 2, 3]).unwrap();
+
+        // То же содержимое, но head смещен на два слота
+        let mut b = RingBuffer::new(4);
+        b.push_slice(&[9, 9]).unwrap();
+        b.pop();
+        b.pop();
+        b.push_slice(&[1, 2, 3]).unwrap();
+        assert_ne!(a.head, b.head);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+
+        let mut c = RingBuffer::new(4);
+        c.push_slice(&[1, 3, 2]).unwrap();
+        assert_ne!(a.fingerprint(), c.fingerprint());
+        c.pop_back();
+        assert_ne!(a.fingerprint(), c.fingerprint());
+
+        let safe = SafeRingBuffer::from_ring(b, String::new());
+        assert_eq!(safe.fingerprint(), a.fingerprint());
+    }
+
+    #[test]
+    fn test_op_error_conversion() {
+        let buffer = SafeRingBuffer::try_new(1).unwrap();
+        assert_eq!(
+            SafeRingBuffer::try_new(0).err(),
+            Some(BufferError::ZeroCapacity)
+        );
+
+        // BufferError поднимается в OpError через ?
+        let push_two = || -> Result<(), OpError> {
+            buffer.push(1)?;
+            buffer.push(2)?;
+            Ok(())
+        };
+        let full = BufferError::Full { capacity: 1 };
+        assert_eq!(push_two(), Err(OpError::Buffer(full)));
+        assert_eq!(
+            buffer.try_push(3),
+            Err(BufferError::Full { capacity: 1 }.into())
+        );
+        assert_eq!(buffer.try_pop(), Ok(1));
+
+        match buffer.try_pop() {
+            Err(OpError::Buffer(BufferError::Empty)) => {}
+            other => panic!("ожидали Empty, получили {other:?}"),
+        }
+        assert_eq!(OpError::Closed.to_string(), "ring buffer closed");
+        assert_eq!(
+            OpError::LockTimeout.to_string(),
+            "ring buffer operation timed out"
+        );
+        assert_eq!(
+            OpError::from(BufferError::Full { capacity: 1 }).to_string(),
+            "ring buffer full (capacity 1)"
+        );
+    }
+
+    #[test]
+    fn test_try_push_reports_poisoned() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        let poisoner = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.transaction(|_| panic!("отравляем мьютекс")))
+        };
+        assert!(poisoner.join().is_err());
+
+        assert_eq!(buffer.try_push(1), Err(OpError::Poisoned));
+        assert_eq!(buffer.try_pop(), Err(OpError::Poisoned));
+    }
+
+    #[test]
+    fn test_blocking_ops_report_op_errors() {
+        // Таймаут и закрытие
+        let buffer = SafeRingBuffer::new(2);
+        assert_eq!(
+            buffer.pop_timeout(Duration::from_millis(5)),
+            Err(OpError::LockTimeout)
+        );
+        buffer.close();
+        assert_eq!(buffer.pop_blocking(), Err(OpError::Closed));
+        assert_eq!(
+            buffer.pop_timeout(Duration::from_secs(5)),
+            Err(OpError::Closed)
+        );
+        assert_eq!(buffer.push_blocking(1), Err(OpError::Closed));
+
+        // Отравление до вызова
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let poisoner = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.transaction(|_| panic!("отравляем мьютекс")))
+        };
+        assert!(poisoner.join().is_err());
+        assert_eq!(buffer.pop_blocking(), Err(OpError::Poisoned));
+        assert_eq!(buffer.pop_timeout(Duration::ZERO), Err(OpError::Poisoned));
+        assert_eq!(buffer.drain_all_blocking(), Err(OpError::Poisoned));
+        assert_eq!(buffer.recv_many_blocking(2), Err(OpError::Poisoned));
+        assert!(matches!(buffer.peek_blocking(), Err(OpError::Poisoned)));
+        assert_eq!(buffer.push_blocking(1), Err(OpError::Poisoned));
+
+        // Отравление во время ожидания: ждущий просыпается с Poisoned
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let reader = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.pop_blocking())
+        };
+        thread::sleep(Duration::from_millis(20));
+        let poisoner = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                buffer.transaction(|ring| {
+                    ring.push(1).unwrap(); // Рост размера будит читателя
+                    panic!("отравляем мьютекс");
+                })
+            })
+        };
+        assert!(poisoner.join().is_err());
+        assert_eq!(reader.join().unwrap(), Err(OpError::Poisoned));
+    }
+
+    #[test]
+    fn test_enumerate_iter_wrapped() {
+        let mut buffer = RingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.pop();
+        buffer.pop();
+        buffer.push_slice(&[4, 5]).unwrap(); // Физически: [5, _, 3, 4]
+        assert_eq!(buffer.head, 2);
+
+        let pairs: Vec<(usize, u8)> = buffer.enumerate_iter().map(|(i, v)| (i, *v)).collect();
+        assert_eq!(pairs, vec![(0, 3), (1, 4), (2, 5)]);
+        assert_eq!(*buffer.data.get(0), Some(5)); // Физический слот 0 — логический 2
+    }
+
+    #[test]
+    fn test_pop_spin() {
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        assert_eq!(buffer.pop_spin(1000), None); // Пустой буфер — сдаемся
+
+        let writer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(5));
+                buffer.push(7).unwrap();
+            })
+        };
+        // Окно ожидания заведомо больше задержки писателя
+        assert_eq!(buffer.pop_spin(usize::MAX), Some(7));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_until_empty() {
+        let buffer = Arc::new(SafeRingBuffer::new(8));
+        buffer.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let waiter = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                buffer.wait_until_empty();
+                let len = buffer.len();
+                done_tx.send(()).unwrap();
+                len
+            })
+        };
+
+        // Потребитель вычитывает буфер в фоне с паузами
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                while buffer.pop().is_some() {
+                    thread::sleep(Duration::from_millis(2));
+                }
+            })
+        };
+
+        thread::sleep(Duration::from_millis(5));
+        assert!(done_rx.try_recv().is_err()); // Еще не все вычитано
+        consumer.join().unwrap();
+        assert_eq!(waiter.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_close_rejects_try_push() {
+        let buffer = SafeRingBuffer::new(2);
+        buffer.push(1).unwrap();
+        buffer.close();
+        assert!(buffer.is_closed());
+        assert_eq!(buffer.try_push(2), Err(OpError::Closed));
+        assert_eq!(buffer.try_pop(), Ok(1)); // Данные дочитываются
+        buffer.wait_until_empty(); // Закрыт и пуст — не ждем
+    }
+
+    #[test]
+    fn test_new_pow2() {
+        let mut buffer = RingBuffer::new_pow2(5);
+        assert_eq!(buffer.capacity, 8);
+        assert_eq!(buffer.indexing, Indexing::Mask(MaskStrategy { mask: 7 }));
+        assert_eq!(RingBuffer::new_pow2(8).capacity, 8);
+
+        // Запрос больше старшей степени двойки: AllocFailed вместо паники
+        let pow2 = RingBuffer::try_new_pow2(5).unwrap();
+        assert_eq!(pow2.indexing, Indexing::Mask(MaskStrategy { mask: 7 }));
+        assert_eq!(
+            RingBuffer::try_new_pow2(usize::MAX / 2 + 2).err(),
+            Some(BufferError::AllocFailed)
+        );
+        assert!(std::panic::catch_unwind(|| RingBuffer::new_pow2(usize::MAX)).is_err());
+
+        // Несколько полных оборотов по кольцу
+        for round in 0..3u8 {
+            for i in 0..8 {
+                buffer.push(round * 10 + i).unwrap();
+            }
+            assert!(buffer.is_full());
+            assert_eq!(buffer.pop(), Some(round * 10));
+            buffer.push(round * 10 + 8).unwrap();
+            let expected: Vec<u8> = (1..=8).map(|i| round * 10 + i).collect();
+            assert_eq!(buffer.iter().collect::<Vec<_>>(), expected);
+            buffer.clear();
+        }
+    }
+
+    #[test]
+    #[ignore = "микробенчмарк, запускать вручную с --release"]
+    fn bench_pow2_vs_modulo() {
+        fn run(mut buffer: RingBuffer) -> Duration {
+            let start = Instant::now();
+            for i in 0..10_000_000u32 {
+                buffer.push_overwrite(i as u8);
+                if i % 3 == 0 {
+                    buffer.pop();
+                }
+            }
+            start.elapsed()
+        }
+        let modulo = run(RingBuffer::new(1000));
+        let mask = run(RingBuffer::new_pow2(1000));
+        println!("modulo: {modulo:?}, mask: {mask:?}");
+    }
+
+    #[test]
+    fn test_pop_slice() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[9, 9, 9, 1]).unwrap();
+        buffer.pop_slice(&mut [0; 3]);
+        buffer.push_slice(&[2, 3]).unwrap(); // Содержимое переходит через границу
+
+        let mut out = [0; 2];
+        assert_eq!(buffer.pop_slice(&mut out), 2);
+        assert_eq!(out, [1, 2]);
+        let mut out = [0; 5];
+        assert_eq!(buffer.pop_slice(&mut out), 1); // Больше, чем есть
+        assert_eq!(out[0], 3);
+        assert_eq!(buffer.pop_slice(&mut out), 0);
+    }
+
+    // Передача TOTAL байт через буфер двумя производителями и двумя
+    // потребителями; batch = 1 — поэлементные push/pop, иначе push_slice/pop_slice
+    fn transfer(batch: usize) -> Duration {
+        const TOTAL: usize = 2_000_000;
+        let buffer = Arc::new(SafeRingBuffer::new(1024));
+        let received = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            let producer = Arc::clone(&buffer);
+            handles.push(thread::spawn(move || {
+                let chunk = vec![7u8; batch];
+                for _ in 0..TOTAL / 2 / batch {
+                    if batch == 1 {
+                        while producer.push(7).is_err() {
+                            thread::yield_now();
+                        }
+                    } else {
+                        while producer.push_slice(&chunk).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                }
+            }));
+            let consumer = Arc::clone(&buffer);
+            let received = Arc::clone(&received);
+            handles.push(thread::spawn(move || {
+                let mut out = vec![0u8; batch];
+                while received.load(Ordering::Relaxed) < TOTAL {
+                    let count = if batch == 1 {
+                        usize::from(consumer.pop().is_some())
+                    } else {
+                        consumer.pop_slice(&mut out)
+                    };
+                    if count == 0 {
+                        thread::yield_now();
+                    }
+                    received.fetch_add(count, Ordering::Relaxed);
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        start.elapsed()
+    }
+
+    #[test]
+    #[ignore = "бенчмарк под конкуренцией, запускать вручную с --release"]
+    fn bench_batch_vs_per_element() {
+        let per_element = transfer(1);
+        let batched = transfer(64);
+        println!("per-element: {per_element:?}, batched: {batched:?}");
+        // Пакет берет блокировку один раз на 64 байта; с большим запасом
+        // он обязан быть хотя бы вдвое быстрее поэлементной передачи
+        assert!(
+            batched * 2 < per_element,
+            "пакетная передача не быстрее поэлементной"
+        );
+    }
+
+    #[cfg(feature = "log")]
+    mod log_capture {
+        use std::sync::Mutex;
+        use std::thread::{self, ThreadId};
+
+        // Логгер, запоминающий записи вместе с потоком-источником:
+        // тесты идут параллельно, и каждый смотрит только на свои записи
+        pub struct CaptureLogger {
+            pub records: Mutex<Vec<(ThreadId, String)>>,
+        }
+
+        impl log::Log for CaptureLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                if record.target() == "ring_buffer" {
+                    let message = record.args().to_string();
+                    let id = thread::current().id();
+                    self.records.lock().unwrap().push((id, message));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        pub static LOGGER: CaptureLogger = CaptureLogger {
+            records: Mutex::new(Vec::new()),
+        };
+
+        pub fn install() {
+            let _ = log::set_logger(&LOGGER);
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+
+        pub fn records_of_current_thread() -> Vec<String> {
+            let id = thread::current().id();
+            let records = LOGGER.records.lock().unwrap();
+            records
+                .iter()
+                .filter(|(thread, _)| *thread == id)
+                .map(|(_, message)| message.clone())
+                .collect()
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn test_log_trace_records() {
+        log_capture::install();
+        let buffer = SafeRingBuffer::new(2);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push_overwrite(3).unwrap();
+        buffer.pop();
+        buffer.push_front(4).unwrap();
+        buffer.pop_back();
+        buffer.insert_sorted(5).unwrap();
+
+        assert_eq!(
+            log_capture::records_of_current_thread(),
+            vec![
+                "push size=1 capacity=2",
+                "push size=2 capacity=2",
+                "push_overwrite size=2 capacity=2",
+                "pop size=1 capacity=2",
+                "push_front size=2 capacity=2",
+                "pop_back size=1 capacity=2",
+                "insert_sorted size=2 capacity=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_with_capacity() {
+        let buffer = RingBuffer::try_with_capacity(5).unwrap();
+        assert_eq!(buffer.capacity, 5);
+        assert_eq!(buffer.data.len(), 5);
+        assert!(buffer.is_empty());
+
+        assert_eq!(
+            RingBuffer::try_with_capacity(0).err(),
+            Some(BufferError::ZeroCapacity)
+        );
+        // Размер хранилища переполняет usize — выделение невозможно
+        assert_eq!(
+            RingBuffer::try_with_capacity(usize::MAX).err(),
+            Some(BufferError::AllocFailed)
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.pop();
+        buffer.push(4).unwrap(); // Содержимое перешло через границу
+
+        assert_eq!(buffer.resize(6), Ok(()));
+        buffer.push_slice(&[5, 6, 7]).unwrap();
+        // Не влезет 6 элементов
+        assert_eq!(buffer.resize(5), Err(BufferError::Full { capacity: 5 }));
+        assert_eq!(buffer.resize(usize::MAX), Err(BufferError::AllocFailed));
+        assert_eq!(drain(&buffer), vec![2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_sampler() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let buffer = {
+            let seen = Arc::clone(&seen);
+            SafeRingBuffer::new(4).with_sampler(10, move |v| seen.lock().unwrap().push(v))
+        };
+
+        for value in 1..=100 {
+            buffer.push(value).unwrap();
+            buffer.pop();
+        }
+        // Неудачные записи не считаются
+        buffer.push_slice(&[1, 2, 3, 4]).unwrap();
+        for _ in 0..20 {
+            assert!(buffer.push(0).is_err());
+        }
+        let expected: Vec<u8> = (1..=10).map(|i| i * 10).collect();
+        assert_eq!(*seen.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn fuzz_invariants() {
+        for seed in 1..=20u64 {
+            let mut rng = XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let capacity = 1 + rng.below(8) as usize;
+            let mut buffer = RingBuffer::new(capacity);
+            // Эталонная модель с теми же ограничениями по вместимости
+            let mut model: VecDeque<u8> = VecDeque::new();
+
+            for step in 0..5000 {
+                let value = rng.next() as u8;
+                let context = format!("seed {seed}, шаг {step}");
+                match rng.below(8) {
+                    0 | 1 => {
+                        let expected = if model.len() < capacity {
+                            model.push_back(value);
+                            Ok(())
+                        } else {
+                            Err(BufferError::Full { capacity })
+                        };
+                        assert_eq!(buffer.push(value), expected, "{context}");
+                    }
+                    2 => assert_eq!(buffer.pop(), model.pop_front(), "{context}"),
+                    3 => assert_eq!(buffer.pop_back(), model.pop_back(), "{context}"),
+                    4 => {
+                        let expected = if model.len() < capacity {
+                            model.push_front(value);
+                            Ok(())
+                        } else {
+                            Err(BufferError::Full { capacity })
+                        };
+                        assert_eq!(buffer.push_front(value), expected, "{context}");
+                    }
+                    5 => {
+                        let evicted = if model.len() == capacity {
+                            model.pop_front()
+                        } else {
+                            None
+                        };
+                        model.push_back(value);
+                        assert_eq!(buffer.push_overwrite(value), evicted, "{context}");
+                    }
+                    6 => {
+                        let len = rng.below(4) as usize;
+                        let values: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+                        let expected = if model.len() + len <= capacity {
+                            model.extend(&values);
+                            Ok(())
+                        } else {
+                            Err(BufferError::Full { capacity })
+                        };
+                        assert_eq!(buffer.push_slice(&values), expected, "{context}");
+                    }
+                    _ => {
+                        if rng.below(10) == 0 {
+                            buffer.clear();
+                            model.clear();
+                        } else {
+                            assert_eq!(buffer.peek(), model.front().copied(), "{context}");
+                        }
+                    }
+                }
+
+                assert_eq!(buffer.len(), model.len(), "{context}");
+                let sum: u64 = model.iter().map(|&v| v as u64).sum();
+                assert_eq!(buffer.window_sum(), sum, "{context}");
+            }
+            assert_eq!(buffer.iter().collect::<VecDeque<_>>(), model);
+        }
+    }
+
+    #[test]
+    fn test_full_error_reports_capacity() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.push_slice(&[1, 2, 3, 4, 5]).unwrap();
+        let error = buffer.push(6).unwrap_err();
+        assert_eq!(error, BufferError::Full { capacity: 5 });
+        assert_ne!(error, BufferError::Full { capacity: 4 }); // capacity участвует в ==
+        assert_eq!(error.to_string(), "ring buffer full (capacity 5)");
+    }
+
+    #[test]
+    fn test_begin_push_commit() {
+        let buffer = SafeRingBuffer::new(2);
+        let ticket = buffer.begin_push(7).unwrap();
+        assert_eq!(buffer.pop(), None); // Зарезервированное значение невидимо
+        buffer.push(1).unwrap();
+        // Резерв занимает место: второй слот уже отдан
+        assert_eq!(buffer.push(2), Err(BufferError::Full { capacity: 2 }));
+
+        ticket.commit().unwrap();
+        assert_eq!(drain(&buffer), vec![1, 7]);
+
+        // commit учитывается наблюдателями, как обычная запись
+        let buffer = SafeRingBuffer::new(2).with_reservoir(4, 1);
+        buffer.begin_push(5).unwrap().commit().unwrap();
+        assert_eq!(buffer.sample(), vec![5]);
+
+        // После close commit отклоняется, а резерв освобождается
+        let ticket = buffer.begin_push(6).unwrap();
+        buffer.close();
+        assert_eq!(ticket.commit(), Err(OpError::Closed));
+        assert_eq!(drain(&buffer), vec![5]);
+        assert_eq!(buffer.acquire().free(), 2);
+    }
+
+    #[test]
+    fn test_begin_push_rollback() {
+        let buffer = SafeRingBuffer::new(1);
+        buffer.begin_push(7).unwrap().rollback();
+        assert_eq!(drain(&buffer), vec![]);
+
+        {
+            let _ticket = buffer.begin_push(8).unwrap();
+            assert!(buffer.begin_push(9).is_err()); // Единственный слот зарезервирован
+        } // Drop без commit освобождает резерв
+        assert_eq!(buffer.push(3), Ok(()));
+        assert_eq!(drain(&buffer), vec![3]);
+
+        // Drop внутри transaction: без паники, резерв снимается при выходе
+        let ticket = buffer.begin_push(8).unwrap();
+        buffer.transaction(|ring| {
+            drop(ticket);
+            assert!(ring.is_full()); // Пока держим мьютекс, резерв на месте
+        });
+        assert_eq!(buffer.push(4), Ok(()));
+        assert_eq!(drain(&buffer), vec![4]);
+
+        // Нулевая вместимость — ошибка по StrictMode, как у push
+        let zero = SafeRingBuffer::new(0).with_strict_mode(StrictMode::Error);
+        assert!(matches!(zero.begin_push(1), Err(BufferError::ZeroCapacity)));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let a = SafeRingBuffer::new(2);
+        let b = SafeRingBuffer::new(1);
+        let c = SafeRingBuffer::new(3);
+
+        // Повтор буфера в списке не приводит к повторной блокировке
+        assert_eq!(broadcast(1, &[&a, &b, &c, &a]), Ok(()));
+        assert_eq!(
+            broadcast(2, &[&a, &b, &c]),
+            Err(BufferError::Full { capacity: 1 })
+        );
+
+        // b был полон — ни один буфер не получил 2
+        assert_eq!(drain(&a), vec![1]);
+        assert_eq!(drain(&b), vec![1]);
+        assert_eq!(drain(&c), vec![1]);
+    }
+
+    #[test]
+    fn test_drain_merge_sorted() {
+        let a = SafeRingBuffer::new(2);
+        let b = SafeRingBuffer::new(2);
+        let c = SafeRingBuffer::new(1);
+        let empty = SafeRingBuffer::new(1);
+        a.push_slice(&[1, 4]).unwrap();
+        b.push_slice(&[2, 3]).unwrap();
+        c.push(5).unwrap();
+
+        assert_eq!(
+            drain_merge_sorted(&[&a, &empty, &b, &c]),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(a.is_empty() && b.is_empty() && c.is_empty());
+        // Одни пустые буферы (или ни одного) дают пустой результат
+        assert!(drain_merge_sorted(&[&empty, &a]).is_empty());
+        assert!(drain_merge_sorted(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_pop_expected() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[0xAA, 0x01]).unwrap();
+
+        assert_eq!(buffer.pop_expected(0xAA), Ok(()));
+        assert_eq!(
+            buffer.pop_expected(0x02),
+            Err(BufferError::Unexpected { found: 0x01 })
+        );
+        assert_eq!(buffer.pop(), Some(0x01)); // Голова не тронута
+        assert_eq!(buffer.pop_expected(0x01), Err(BufferError::Empty));
+    }
+
+    #[test]
+    fn test_on_occupancy() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let buffer = {
+            let seen = Arc::clone(&seen);
+            SafeRingBuffer::new(4).on_occupancy(move |f| seen.lock().unwrap().push(f))
+        };
+
+        for value in 0..4 {
+            buffer.push(value).unwrap();
+        }
+        assert!(buffer.push(4).is_err()); // Размер не изменился — без вызова
+        buffer.pop();
+        buffer.pop();
+
+        assert_eq!(*seen.lock().unwrap(), vec![0.25, 0.5, 0.75, 1.0, 0.75, 0.5]);
+    }
+
+    #[test]
+    fn test_drain_runs() {
+        let buffer = SafeRingBuffer::new(8);
+        buffer.push_slice(&[1, 1, 2, 3, 3, 3]).unwrap();
+        assert_eq!(buffer.drain_runs(), vec![(1, 2), (2, 1), (3, 3)]);
+        assert!(buffer.is_empty());
+
+        buffer.push_slice(&[1, 2, 1]).unwrap();
+        assert_eq!(buffer.drain_runs(), vec![(1, 1), (2, 1), (1, 1)]);
+        assert_eq!(buffer.drain_runs(), vec![]);
+    }
+
+    #[test]
+    fn test_contents_eq() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.pop();
+        buffer.push(4).unwrap(); // Переход через границу
+
+        assert!(buffer.contents_eq(&[2, 3, 4]));
+        assert!(!buffer.contents_eq(&[2, 3]));
+        assert!(!buffer.contents_eq(&[2, 3, 4, 5]));
+        assert!(!buffer.contents_eq(&[2, 9, 4]));
+        assert_eq!(drain(&buffer), vec![2, 3, 4]); // Ничего не извлечено
+        assert!(buffer.contents_eq(&[]));
+    }
+
+    #[test]
+    fn test_index_strategies_agree() {
+        let modulo = ModuloStrategy { capacity: 8 };
+        let mask = MaskStrategy { mask: 7 };
+        for i in 0..8 {
+            assert_eq!(modulo.advance(i), mask.advance(i));
+            for by in 0..8 {
+                assert_eq!(modulo.offset(i, by), mask.offset(i, by));
+            }
+        }
+        assert_eq!(modulo.advance(7), 0);
+        assert_eq!(mask.advance(7), 0);
+    }
+
+    #[test]
+    fn test_index_arithmetic_near_usize_max() {
+        // Граница capacity - 1 -> 0 без выделения памяти под такой буфер
+        let capacity = usize::MAX;
+        let modulo = ModuloStrategy { capacity };
+        assert_eq!(modulo.advance(capacity - 1), 0);
+        assert_eq!(modulo.advance(capacity - 2), capacity - 1);
+        assert_eq!(modulo.offset(capacity - 1, 1), 0);
+        assert_eq!(modulo.offset(capacity - 3, capacity - 1), capacity - 4);
+        assert_eq!(modulo.offset(5, capacity - 6), capacity - 1);
+
+        let mask = MaskStrategy {
+            mask: (1usize << (usize::BITS - 1)) - 1,
+        };
+        assert_eq!(mask.advance(mask.mask), 0);
+        assert_eq!(mask.offset(mask.mask, mask.mask), mask.mask - 1);
+    }
+
+    #[test]
+    fn test_many_wraparounds_large_capacity() {
+        // Вместимость не степень двойки, десятки полных оборотов кольца
+        let capacity = 40_009;
+        let mut buffer = RingBuffer::new(capacity);
+        let mut model = VecDeque::new();
+        let mut rng = XorShift(7);
+        for step in 0..2_000_000u32 {
+            let value = step as u8;
+            if rng.below(2) == 0 {
+                assert_eq!(buffer.pop(), model.pop_front());
+            } else if buffer.push(value).is_ok() {
+                model.push_back(value);
+            } else {
+                assert_eq!(model.len(), capacity);
+            }
+        }
+        assert!(buffer.pushed > 20 * capacity as u64);
+        assert_eq!(buffer.len(), model.len());
+        assert!(buffer.iter().eq(model.iter().copied()));
+
+        // Один и тот же сценарий на обеих стратегиях дает одинаковые pop
+        let mut a = RingBuffer::new(8);
+        let mut b = RingBuffer::new_pow2(8);
+        assert_ne!(a.indexing, b.indexing);
+        let mut rng = XorShift(42);
+        for _ in 0..2000 {
+            let value = rng.next() as u8;
+            match rng.below(4) {
+                0 | 1 => assert_eq!(a.push(value), b.push(value)),
+                2 => assert_eq!(a.pop(), b.pop()),
+                _ => assert_eq!(a.push_overwrite(value), b.push_overwrite(value)),
+            }
+            assert!(a.iter().eq(b.iter()));
+        }
+    }
+
+    #[test]
+    fn test_spurious_wakeups_are_rechecked() {
+        // Лишние notify_all без выполнения условия
+        let spurious = |buffer: &SafeRingBuffer| {
+            for _ in 0..5 {
+                thread::sleep(Duration::from_millis(5));
+                buffer.not_empty.notify_all();
+                buffer.not_full.notify_all();
+            }
+        };
+
+        // pop_blocking: данных нет — поток должен снова уснуть
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let reader = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.pop_blocking())
+        };
+        spurious(&buffer);
+        assert!(!reader.is_finished());
+        buffer.push(2).unwrap();
+        assert_eq!(reader.join().unwrap(), Ok(2));
+
+        // wait_until_empty: буфер не пуст — поток должен снова уснуть
+        buffer.push(9).unwrap();
+        let waiter = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.wait_until_empty())
+        };
+        spurious(&buffer);
+        assert!(!waiter.is_finished());
+        assert_eq!(buffer.pop(), Some(9));
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn test_contiguous_front() {
+        let mut ring = RingBuffer::new(5);
+        ring.push_slice(&[1, 2, 3]).unwrap();
+        assert_eq!(ring.contiguous_front(), &[Some(1), Some(2), Some(3)]);
+        assert!(ring.as_slices().1.is_empty());
+
+        ring.pop();
+        ring.pop();
+        ring.push_slice(&[4, 5, 6]).unwrap(); // Физически: [6, _, 3, 4, 5]
+        assert_eq!(ring.contiguous_front(), &[Some(3), Some(4), Some(5)]);
+        assert_eq!(ring.as_slices().1, &[Some(6)]);
+
+        let buffer = SafeRingBuffer::from_ring(ring, String::new());
+        let mut out = [0; 8];
+        assert_eq!(buffer.pop_contiguous(&mut out[..2]), 2); // Ограничено out
+        assert_eq!(out[..2], [3, 4]);
+        assert_eq!(buffer.pop_contiguous(&mut out), 1); // До конца массива
+        assert_eq!(out[0], 5);
+        assert_eq!(buffer.pop_contiguous(&mut out), 1); // Остаток с начала
+        assert_eq!(out[0], 6);
+        assert_eq!(buffer.pop_contiguous(&mut out), 0);
+    }
+
+    #[test]
+    fn test_with_validator() {
+        let buffer = SafeRingBuffer::new(2).with_validator(|&v| v != 0);
+        assert_eq!(buffer.push(0), Err(BufferError::Invalid));
+        assert_eq!(buffer.push_slice(&[1, 0]), Err(BufferError::Invalid));
+        assert!(buffer.is_empty()); // Отклоненные записи ничего не меняют
+
+        buffer.push(1).unwrap();
+        buffer.push_front(2).unwrap();
+        // Полный буфер: некорректное значение все равно получает Invalid
+        assert_eq!(buffer.push(0), Err(BufferError::Invalid));
+        assert_eq!(buffer.push(3), Err(BufferError::Full { capacity: 2 }));
+        assert_eq!(drain(&buffer), vec![2, 1]);
+
+        // Обходные пути записи тоже проверяют значения
+        assert!(matches!(buffer.begin_push(0), Err(BufferError::Invalid)));
+        assert_eq!(buffer.swap_contents(vec![1, 0]), Err(BufferError::Invalid));
+        assert_eq!(broadcast(0, &[&buffer]), Err(BufferError::Invalid));
+        assert_eq!(buffer.push_overwrite(0), Err(BufferError::Invalid));
+        assert_eq!(buffer.push_evict_n(0, 1), Err(BufferError::Invalid));
+        assert!(buffer.is_empty());
+
+        // Затирающие методы на полном буфере: Invalid и ничего не вытеснено,
+        // пакетные отклоняют весь срез целиком
+        buffer.push_slice(&[1, 2]).unwrap();
+        assert_eq!(buffer.push_overwrite(0), Err(BufferError::Invalid));
+        assert_eq!(
+            buffer.push_slice_overwrite(&[3, 0]),
+            Err(BufferError::Invalid)
+        );
+        assert_eq!(buffer.overwrite_with(&[4, 0, 5]), Err(BufferError::Invalid));
+        assert_eq!(buffer.fill_pattern(&[6, 0]), Err(BufferError::Invalid));
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(buffer.push_slice_overwrite(&[3]), Ok(vec![1]));
+        assert_eq!(drain(&buffer), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_capacity_history() {
+        let buffer = SafeRingBuffer::new(4);
+        assert!(buffer.capacity_history().is_empty());
+
+        buffer.push(1).unwrap();
+        buffer.resize(8).unwrap();
+        buffer.resize(16).unwrap();
+        assert!(buffer.maybe_shrink()); // 1 из 16 — уменьшаем вдвое
+        assert!(buffer.resize(0).is_err()); // Неудачный resize в историю не попадает
+
+        let history = buffer.capacity_history();
+        let transitions: Vec<(usize, usize)> = history.iter().map(|&(_, o, n)| (o, n)).collect();
+        assert_eq!(transitions, vec![(4, 8), (8, 16), (16, 8)]);
+        assert!(history.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        // Длина истории ограничена, хранятся самые свежие записи
+        for cap in 0..CAPACITY_HISTORY_LEN + 10 {
+            buffer.resize(10 + cap % 2).unwrap();
+        }
+        let history = buffer.capacity_history();
+        assert_eq!(history.len(), CAPACITY_HISTORY_LEN);
+        let &(_, old, new) = history.last().unwrap();
+        assert_eq!((old, new), (10, 11));
+    }
+
+    #[test]
+    fn test_push_blocking_waits_for_space() {
+        let buffer = Arc::new(SafeRingBuffer::new(1));
+        buffer.push(1).unwrap();
+
+        let writer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.push_blocking(2))
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(buffer.pop(), Some(1)); // Освобождаем место писателю
+        assert_eq!(writer.join().unwrap(), Ok(()));
+        assert_eq!(buffer.pop(), Some(2));
+
+        // После закрытия ожидание прерывается ошибкой
+        buffer.push(3).unwrap();
+        let writer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.push_blocking(4))
+        };
+        thread::sleep(Duration::from_millis(20));
+        buffer.close();
+        assert_eq!(writer.join().unwrap(), Err(OpError::Closed));
+        assert_eq!(drain(&buffer), vec![3]);
+    }
+
+    // Два производителя пишут по PUSHES_PER_PRODUCER значений в буфер с
+    // большой вместимостью, CONSUMERS потребителей все вычитывают.
+    // Потребителей больше, чтобы хоть один всегда ждал внутри pop_blocking,
+    // даже когда планировщик вытесняет поток между вызовами. Возвращает
+    // наибольший перекос (записано минус извлечено), замеченный после записи.
+    fn run_producers_consumers(buffer: SafeRingBuffer) -> usize {
+        const PUSHES_PER_PRODUCER: usize = 5_000;
+        const CONSUMERS: usize = 8;
+        let buffer = Arc::new(buffer);
+        let peak = Arc::new(AtomicUsize::new(0));
+        let pops = Arc::new(AtomicUsize::new(0));
+        // Производители стартуют, когда все потребители уже ждут данных
+        let ready = Arc::new(AtomicUsize::new(0));
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let (buffer, pops, ready) =
+                    (Arc::clone(&buffer), Arc::clone(&pops), Arc::clone(&ready));
+                thread::spawn(move || {
+                    ready.fetch_add(1, Ordering::Relaxed);
+                    while buffer.pop_blocking().is_ok() {
+                        pops.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        let producers: Vec<_> = (0..2)
+            .map(|_| {
+                let (buffer, peak, ready) =
+                    (Arc::clone(&buffer), Arc::clone(&peak), Arc::clone(&ready));
+                thread::spawn(move || {
+                    while ready.load(Ordering::Relaxed) < CONSUMERS {
+                        thread::yield_now();
+                    }
+                    for _ in 0..PUSHES_PER_PRODUCER {
+                        buffer.push_blocking(1).unwrap();
+                        peak.fetch_max(buffer.len_relaxed(), Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in producers {
+            handle.join().unwrap();
+        }
+        buffer.close(); // Потребители дочитывают остаток и выходят
+        for handle in consumers {
+            handle.join().unwrap();
+        }
+        assert_eq!(pops.load(Ordering::Relaxed), 2 * PUSHES_PER_PRODUCER);
+        peak.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_alternation_balances_progress() {
+        // Без чередования писатели вправе уйти вперед на всю вместимость,
+        // с чередованием уступают ждущим читателям — перекос меньше
+        let free = run_producers_consumers(SafeRingBuffer::new(1 << 20));
+        let alternating = run_producers_consumers(SafeRingBuffer::new(1 << 20).with_alternation());
+        assert!(
+            alternating < free,
+            "перекос с чередованием {alternating}, без него {free}"
+        );
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[9, 9, 1, 2]).unwrap();
+        buffer.pop();
+        buffer.pop();
+        buffer.push_slice(&[3, 4]).unwrap(); // Содержимое переходит через границу
+
+        assert_eq!(buffer.extract_if(|v| v % 2 == 0), vec![2, 4]);
+        assert_eq!(buffer.window_sum(), 4);
+        buffer.push_slice(&[5, 6]).unwrap(); // Освободилось ровно два слота
+        assert_eq!(drain(&buffer), vec![1, 3, 5, 6]);
+
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        assert!(buffer.extract_if(|_| false).is_empty());
+        assert_eq!(buffer.extract_if(|_| true), vec![1, 2, 3]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_rate_counter_window() {
+        // Окно 4 x 50 мс = 0.2 с
+        let mut counter = RateCounter::new(Duration::from_millis(50), 4);
+        let start = counter.origin;
+        for _ in 0..20 {
+            counter.record(start);
+        }
+        for _ in 0..20 {
+            counter.record(start + Duration::from_millis(60));
+        }
+        // 40 событий за окно 0.2 с
+        let rate = counter.rate_per_sec(start + Duration::from_millis(100));
+        assert!((rate - 200.0).abs() < 1e-9, "rate = {rate}");
+
+        // Первая корзина выпала из окна, вторая еще нет
+        let rate = counter.rate_per_sec(start + Duration::from_millis(210));
+        assert!((rate - 100.0).abs() < 1e-9, "rate = {rate}");
+        assert_eq!(
+            counter.rate_per_sec(start + Duration::from_millis(300)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_push_rate_per_sec() {
+        let buffer = SafeRingBuffer::new(64);
+        assert_eq!(buffer.push_rate_per_sec(), 0.0);
+        for _ in 0..2 {
+            buffer.push_slice(&[1; 20]).unwrap();
+            drain(&buffer);
+            thread::sleep(Duration::from_millis(20));
+        }
+        // 40 записей за 10-секундное окно
+        let rate = buffer.push_rate_per_sec();
+        assert!((rate - 4.0).abs() < 1e-9, "rate = {rate}");
+
+        // Затухание до нуля после паузы (сдвигаем часы вместо сна)
+        let inner = buffer.acquire();
+        let later = Instant::now() + Duration::from_secs(PUSH_RATE_WINDOW_SECS as u64 + 1);
+        assert_eq!(inner.push_rate.rate_per_sec(later), 0.0);
+    }
+
+    #[test]
+    fn test_drain_expect() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+
+        assert_eq!(
+            buffer.drain_expect(2),
+            Err(BufferError::Mismatch {
+                expected: 2,
+                actual: 3
+            })
+        );
+        assert_eq!(buffer.len(), 3); // Буфер не тронут
+        assert_eq!(buffer.drain_expect(3), Ok(vec![1, 2, 3]));
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.drain_expect(0), Ok(vec![]));
+    }
+
+    // Стандартный набор операций; возвращает все наблюдаемые результаты
+    fn storage_suite<S: Storage>(buffer: &mut RingBuffer<S>) -> Vec<String> {
+        let mut log = Vec::new();
+        log.push(format!("{:?}", buffer.push_slice(&[1, 2, 3])));
+        log.push(format!("{:?} {:?}", buffer.pop(), buffer.pop()));
+        log.push(format!("{:?}", buffer.push_slice(&[4, 5, 6])));
+        log.push(format!("{:?}", buffer.push(7))); // Полон
+        log.push(format!("{:?}", buffer.as_slices())); // Переход через границу
+        log.push(format!("{:?}", buffer.push_overwrite(8)));
+        log.push(format!("{:?} {:?}", buffer.pop_back(), buffer.peek()));
+        log.push(format!("{:?}", buffer.push_front(9)));
+        log.push(format!("{} {}", buffer.window_sum(), buffer.fingerprint()));
+        log.push(format!("{:?}", buffer.iter().collect::<Vec<_>>()));
+        buffer.clear();
+        log.push(format!("{} {:?}", buffer.len(), buffer.pop()));
+        log
+    }
+
+    #[test]
+    fn test_custom_storage_matches_vec() {
+        let mut custom = RingBuffer::with_storage(ArrayStorage([Some(0xFF); 4]));
+        assert_eq!(custom.capacity, 4);
+        assert!(custom.is_empty()); // Старое содержимое слотов сброшено
+
+        let mut default = RingBuffer::new(4);
+        assert_eq!(storage_suite(&mut custom), storage_suite(&mut default));
+    }
+
+    #[test]
+    fn test_swap_contents() {
+        let buffer = SafeRingBuffer::new(3);
+        assert_eq!(buffer.swap_contents(vec![1, 2]), Ok(vec![]));
+        assert_eq!(buffer.swap_contents(vec![3, 4, 5]), Ok(vec![1, 2]));
+
+        assert_eq!(
+            buffer.swap_contents(vec![6, 7, 8, 9]),
+            Err(BufferError::Full { capacity: 3 })
+        );
+        assert_eq!(drain(&buffer), vec![3, 4, 5]); // Неудачная замена ничего не тронула
+    }
+
+    #[test]
+    fn test_with_head() {
+        let buffer = SafeRingBuffer::new(2);
+        assert!(buffer.with_head(|head| head.is_none()));
+
+        buffer.push_slice(&[0xA5, 0x01]).unwrap();
+        // Старший полубайт головы, без извлечения
+        assert_eq!(buffer.with_head(|head| head.map(|v| v >> 4)), Some(0xA));
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    fn test_auto_compact() {
+        let fill = |buffer: &SafeRingBuffer| {
+            buffer.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+            for _ in 0..5 {
+                buffer.pop(); // Остается 3 из 8 — ниже половины
+            }
+        };
+
+        let compacting = SafeRingBuffer::new(8).with_auto_compact(0.5);
+        fill(&compacting);
+        compacting.transaction(|buffer| {
+            assert_eq!(buffer.head, 0);
+            assert_eq!(buffer.contiguous_front(), &[Some(6), Some(7), Some(8)]);
+        });
+        assert_eq!(compacting.window_sum(), 21);
+        compacting.push_slice(&[9; 5]).unwrap(); // tail указывает за содержимое
+        assert_eq!(drain(&compacting), vec![6, 7, 8, 9, 9, 9, 9, 9]);
+
+        let plain = SafeRingBuffer::new(8);
+        fill(&plain);
+        assert_eq!(plain.transaction(|buffer| buffer.head), 5);
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        let buffer = SafeRingBuffer::new(3);
+        assert_eq!(buffer.head_seq(), None);
+        buffer.push_slice(&[10, 11, 12]).unwrap();
+        assert_eq!(buffer.head_seq(), Some(0));
+        assert_eq!(buffer.pop_seq(), Some((0, 10)));
+
+        // Номер, который держит вызывающий, устаревает после pop
+        let handle = buffer.head_seq().unwrap();
+        assert_eq!(buffer.pop_seq(), Some((handle, 11)));
+        assert_ne!(buffer.head_seq(), Some(handle));
+
+        // Переход через границу и перестройка номера не сбивают
+        buffer.push_slice(&[13, 14]).unwrap();
+        buffer.resize(5).unwrap();
+        buffer.push(15).unwrap();
+        let popped: Vec<(u64, u8)> = std::iter::from_fn(|| buffer.pop_seq()).collect();
+        assert_eq!(popped, vec![(2, 12), (3, 13), (4, 14), (5, 15)]);
+
+        // pop_back + push не возвращает старый номер
+        buffer.push(16).unwrap();
+        buffer.pop_back();
+        buffer.push(17).unwrap();
+        assert_eq!(buffer.pop_seq(), Some((7, 17)));
+    }
+
+    #[test]
+    fn test_lossy_drop() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let buffer = {
+            let reports = Arc::clone(&reports);
+            SafeRingBuffer::new(2).with_lossy_drop(Duration::from_millis(50), move |total| {
+                reports.lock().unwrap().push(total)
+            })
+        };
+        assert_eq!(buffer.dropped_total(), 0);
+        buffer.push_slice(&[1, 2]).unwrap();
+
+        // Устойчивое переполнение: все лишнее отброшено, отчет один
+        for value in 0..100 {
+            assert_eq!(buffer.push(value), Ok(()));
+        }
+        assert_eq!(buffer.dropped_total(), 100);
+        assert_eq!(*reports.lock().unwrap(), vec![1]);
+
+        // Следующий интервал — следующий отчет с накопленным итогом
+        thread::sleep(Duration::from_millis(60));
+        buffer.push(3).unwrap();
+        assert_eq!(*reports.lock().unwrap(), vec![1, 101]);
+        assert_eq!(drain(&buffer), vec![1, 2]); // Старые значения не затерты
+
+        // Без режима переполнение — по-прежнему ошибка
+        let plain = SafeRingBuffer::new(1);
+        plain.push(1).unwrap();
+        assert!(plain.push(2).is_err());
+        assert_eq!(plain.dropped_total(), 0);
+    }
+
+    #[test]
+    fn test_drain_if_full() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[1, 2]).unwrap();
+        assert_eq!(buffer.drain_if_full(), None);
+        assert_eq!(buffer.len(), 2); // Неполный буфер не тронут
+
+        buffer.push(3).unwrap();
+        assert_eq!(buffer.drain_if_full(), Some(vec![1, 2, 3]));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+
+        let mut cursor = buffer.cursor();
+        assert_eq!(cursor.peek(), Some(1));
+        assert_eq!(cursor.position(), 0); // peek не продвигает
+        assert_eq!(cursor.next(), Some(1));
+        assert_eq!(cursor.next(), Some(2));
+        assert_eq!(cursor.position(), 2);
+
+        buffer.pop(); // Снимок не видит изменений буфера
+        assert_eq!(cursor.next(), Some(3));
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.peek(), None);
+
+        cursor.reset();
+        assert_eq!(cursor.position(), 0);
+        assert_eq!(cursor.collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(drain(&buffer), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_insert_sorted() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.insert_sorted(5).unwrap(); // В пустой буфер
+        buffer.insert_sorted(2).unwrap(); // Новый минимум
+        buffer.insert_sorted(9).unwrap(); // Новый максимум
+        buffer.insert_sorted(4).unwrap(); // В середину
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![2, 4, 5, 9]);
+
+        // Вставка со сдвигом через границу массива
+        assert_eq!(buffer.pop(), Some(2));
+        buffer.insert_sorted(6).unwrap();
+        buffer.insert_sorted(3).unwrap();
+        assert_eq!(
+            buffer.insert_sorted(1),
+            Err(BufferError::Full { capacity: 5 })
+        );
+        assert_eq!(buffer.window_sum(), 27);
+        assert_eq!(drain(&buffer), vec![3, 4, 5, 6, 9]);
+    }
+
+    #[test]
+    fn test_drain_with_blocks_producers() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+
+        let (inside_tx, inside_rx) = std::sync::mpsc::channel();
+        let producer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                inside_rx.recv().unwrap();
+                buffer.push(4).unwrap(); // Ждет, пока drain_with держит блокировку
+            })
+        };
+
+        let seen = buffer.drain_with(|values| {
+            inside_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            values.to_vec()
+        });
+        producer.join().unwrap();
+
+        assert_eq!(seen, vec![1, 2, 3]); // Ровно содержимое до сброса
+        assert_eq!(drain(&buffer), vec![4]); // Запись прошла уже после очистки
+    }
+
+    #[test]
+    fn test_peek_blocking() {
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        buffer.push_slice(&[1, 2]).unwrap();
+
+        let head = buffer.peek_blocking().unwrap();
+        assert_eq!(head.value(), 1);
+        assert_eq!(head.commit(), 1);
+        assert_eq!(buffer.len(), 1);
+
+        // release оставляет элемент другому потребителю
+        let head = buffer.peek_blocking().unwrap();
+        assert_eq!(head.value(), 2);
+        head.release();
+        let other = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.pop())
+        };
+        assert_eq!(other.join().unwrap(), Some(2));
+
+        // Ожидание на пустом буфере до появления данных
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.peek_blocking().map(PeekGuard::commit))
+        };
+        thread::sleep(Duration::from_millis(20));
+        buffer.push(3).unwrap();
+        assert_eq!(consumer.join().unwrap(), Ok(3));
+
+        buffer.close();
+        assert!(matches!(buffer.peek_blocking(), Err(OpError::Closed)));
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let buffer = SafeRingBuffer::new(4);
+        assert_eq!(buffer.backpressure(), 0.0);
+        buffer.push_slice(&[1, 2]).unwrap();
+        assert_eq!(buffer.backpressure(), 0.5);
+        assert!(!buffer.should_throttle(0.75));
+
+        buffer.push(3).unwrap();
+        assert!(buffer.should_throttle(0.75)); // Ровно на пороге
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.backpressure(), 1.0);
+        assert_eq!(SafeRingBuffer::new(0).backpressure(), 1.0);
+    }
+
+    #[test]
+    fn test_yield_hook_interleaving() {
+        use yield_hook::set_yield_hook;
+
+        // Второй писатель запускается ровно между проверкой места и записью
+        // первого: будь push не атомарен, оба прошли бы проверку в буфер на 1
+        let buffer = Arc::new(SafeRingBuffer::new(1));
+        let (go_tx, go_rx) = std::sync::mpsc::channel::<()>();
+        let rival = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                go_rx.recv().unwrap();
+                buffer.push(2)
+            })
+        };
+
+        set_yield_hook(Some(Box::new(move |point| {
+            if point == "push" {
+                go_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(30)); // Отдаем управление
+            }
+        })));
+        assert_eq!(buffer.push(1), Ok(()));
+        set_yield_hook(None);
+
+        assert_eq!(
+            rival.join().unwrap(),
+            Err(BufferError::Full { capacity: 1 })
+        );
+        assert_eq!(drain(&buffer), vec![1]);
+    }
+
+    #[test]
+    fn test_recv_many_blocking() {
+        let buffer = Arc::new(SafeRingBuffer::new(8));
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.recv_many_blocking(4))
+        };
+        thread::sleep(Duration::from_millis(20));
+        // Пакет целиком, чтобы потребитель проснулся сразу к пяти элементам
+        buffer.push_slice(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(consumer.join().unwrap(), Ok(vec![1, 2, 3, 4]));
+
+        // Есть меньше max — отдается сколько есть
+        assert_eq!(buffer.recv_many_blocking(4), Ok(vec![5]));
+
+        buffer.push(6).unwrap();
+        buffer.close();
+        assert_eq!(buffer.recv_many_blocking(4), Ok(vec![6])); // Дочитываем закрытый
+        assert_eq!(buffer.recv_many_blocking(4), Err(OpError::Closed));
+        assert_eq!(buffer.recv_many_blocking(0), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_snapshot_versioned() {
+        let buffer = SafeRingBuffer::new(3);
+        let (v0, empty) = buffer.snapshot_versioned();
+        assert!(empty.is_empty());
+
+        buffer.push_slice(&[1, 2]).unwrap();
+        let (v1, contents) = buffer.snapshot_versioned();
+        assert!(v1 > v0);
+        assert_eq!(contents, vec![1, 2]);
+
+        // Чтения номер не двигают: тот же номер — то же содержимое
+        buffer.len();
+        buffer.fingerprint();
+        assert_eq!(buffer.snapshot_versioned(), (v1, contents));
+
+        buffer.push_overwrite(3).unwrap();
+        buffer.pop();
+        let (v2, contents) = buffer.snapshot_versioned();
+        assert!(v2 > v1);
+        assert_eq!(contents, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_with_capacity_from_iter() {
+        let mut buffer = RingBuffer::with_capacity_from_iter(5, [1, 2, 3]).unwrap();
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.push_slice(&[4, 5]), Ok(()));
+        assert!(buffer.is_full());
+        assert_eq!(buffer.drain_all(), vec![1, 2, 3, 4, 5]);
+
+        // Итератор останавливается на первом лишнем элементе
+        let mut taken = 0;
+        let counting = (0..10).inspect(|_| taken += 1);
+        assert_eq!(
+            RingBuffer::with_capacity_from_iter(2, counting).unwrap_err(),
+            BufferError::Full { capacity: 2 }
+        );
+        assert_eq!(taken, 3);
+    }
+
+    #[test]
+    fn test_array_ring_buffer() {
+        let mut buffer = ArrayRingBuffer::<1>::new_array();
+        assert_eq!(buffer.capacity, 1);
+        assert_eq!(buffer.push(7), Ok(()));
+        assert_eq!(buffer.push(8), Err(BufferError::Full { capacity: 1 }));
+        assert_eq!(buffer.pop(), Some(7));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = RingBuffer::from(VecDeque::from(vec![1, 2, 3]));
+        let mut b = RingBuffer::new(3);
+        b.push_slice(&[0, 1, 2]).unwrap();
+        b.pop();
+        b.push(3).unwrap(); // Другое физическое расположение, то же содержимое
+        assert!(a.diff(&b).is_empty());
+
+        b.pop_back();
+        b.push(9).unwrap();
+        assert_eq!(a.diff(&b), vec![(2, Some(3), Some(9))]);
+
+        let short = RingBuffer::from(VecDeque::from(vec![1]));
+        assert_eq!(a.diff(&short), vec![(1, Some(2), None), (2, Some(3), None)]);
+        assert_eq!(short.diff(&a), vec![(1, None, Some(2)), (2, None, Some(3))]);
+    }
+
+    #[test]
+    fn test_fill_pattern() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.fill_pattern(&[]).unwrap();
+        assert!(buffer.is_empty());
+
+        buffer.push(9).unwrap(); // Прежнее содержимое затирается
+        buffer.fill_pattern(&[1, 2]).unwrap();
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2, 1, 2, 1]);
+
+        buffer.fill_pattern(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+        assert_eq!(drain(&buffer), vec![1, 2, 3, 4, 5]); // Только префикс
+    }
+
+    #[test]
+    fn test_split_off_back() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3, 4]).unwrap();
+
+        let mut back = buffer.split_off_back(2);
+        assert_eq!(back.capacity, 2);
+        assert_eq!(back.drain_all(), vec![3, 4]);
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![1, 2]);
+
+        let mut all = buffer.split_off_back(10); // Больше, чем есть
+        assert_eq!(all.capacity, 10);
+        assert_eq!(all.drain_all(), vec![1, 2]);
+        assert!(buffer.is_empty());
+
+        buffer.push(5).unwrap();
+        let none = buffer.split_off_back(0);
+        assert!(none.is_empty());
+        assert_eq!(drain(&buffer), vec![5]);
+    }
+
+    #[test]
+    fn test_remaining_and_push_checked() {
+        let buffer = SafeRingBuffer::new(3);
+        assert_eq!(buffer.remaining(), 3);
+        assert_eq!(buffer.push_checked(1), Ok(2));
+        assert_eq!(buffer.push_checked(2), Ok(1));
+        assert_eq!(buffer.remaining(), 3 - buffer.len());
+
+        buffer.pop();
+        assert_eq!(buffer.remaining(), 2);
+        assert_eq!(buffer.push_checked(3), Ok(1));
+        assert_eq!(buffer.push_checked(4), Ok(0));
+        assert_eq!(
+            buffer.push_checked(5),
+            Err(BufferError::Full { capacity: 3 })
+        );
+    }
+
+    #[test]
+    fn test_push_evict_n() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2]).unwrap();
+        assert_eq!(buffer.push_evict_n(3, 2), Ok(vec![])); // Не полон — без вытеснения
+
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.push_evict_n(5, 2), Ok(vec![1, 2]));
+        assert_eq!(buffer.len(), 3); // Осталось место под следующую запись
+        assert_eq!(buffer.push_evict_n(6, 1), Ok(vec![]));
+        assert_eq!(buffer.push_evict_n(7, 1), Ok(vec![3])); // Как push_overwrite
+
+        // Вытеснить больше, чем есть, — очистка и запись
+        assert_eq!(buffer.push_evict_n(8, 10), Ok(vec![4, 5, 6, 7]));
+        assert_eq!(drain(&buffer), vec![8]);
+    }
+
+    #[test]
+    fn test_clear_if_generation() {
+        let buffer = SafeRingBuffer::new(3);
+        buffer.push_slice(&[1, 2]).unwrap();
+        let generation = buffer.generation();
+
+        assert!(buffer.clear_if_generation(generation));
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.generation(), generation + 1);
+
+        buffer.push(3).unwrap();
+        assert!(!buffer.clear_if_generation(generation)); // Устаревшее поколение
+        assert_eq!(buffer.generation(), generation + 1);
+        assert_eq!(drain(&buffer), vec![3]);
+    }
+
+    #[test]
+    fn test_pop_array() {
+        let buffer = SafeRingBuffer::new(5);
+        buffer.push_slice(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(buffer.pop_array::<3>(), Some([1, 2, 3]));
+        assert_eq!(buffer.pop_array::<3>(), None); // Осталось 2
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_array::<0>(), Some([]));
+        assert_eq!(buffer.pop_array::<2>(), Some([4, 5]));
+    }
+
+    #[test]
+    fn test_lock_timing() {
+        let buffer = SafeRingBuffer::new(4).with_lock_timing();
+        assert_eq!(buffer.max_lock_hold(), Duration::ZERO);
+        for value in 0..4 {
+            buffer.push(value).unwrap();
+        }
+        drain(&buffer);
+        assert!(buffer.max_lock_hold() > Duration::ZERO);
+        assert!(buffer.avg_lock_hold() > Duration::ZERO);
+
+        // Медленная работа под блокировкой сразу видна в максимуме
+        buffer.transaction(|_| thread::sleep(Duration::from_millis(20)));
+        assert!(buffer.max_lock_hold() >= Duration::from_millis(20));
+        assert!(buffer.avg_lock_hold() <= buffer.max_lock_hold());
+
+        // Без режима ничего не учитывается
+        let plain = SafeRingBuffer::new(1);
+        plain.push(1).unwrap();
+        assert_eq!(plain.max_lock_hold(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_map() {
+        let mut buffer = RingBuffer::new(4);
+        buffer.push_slice(&[9, 1, 2]).unwrap();
+        buffer.pop();
+        buffer.push_slice(&[3, 255]).unwrap(); // Переход через границу
+
+        let mut mapped = buffer.map(|v| v.wrapping_add(1));
+        assert_eq!(mapped.capacity, 4);
+        assert_eq!(mapped.len(), 4);
+        assert!(mapped.push(7).is_err());
+        assert_eq!(mapped.pop(), Some(2));
+        mapped.push(7).unwrap(); // Новый буфер принимает записи
+        assert_eq!(mapped.drain_all(), vec![3, 4, 0, 7]);
+    }
+
+    #[test]
+    fn test_drain_all_blocking() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.drain_all_blocking())
+        };
+        thread::sleep(Duration::from_millis(20));
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        assert_eq!(consumer.join().unwrap(), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_drain_all_blocking_closed() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.drain_all_blocking())
+        };
+        thread::sleep(Duration::from_millis(20));
+        buffer.close(); // Будит ожидающего
+        assert_eq!(consumer.join().unwrap(), Err(OpError::Closed));
+        assert_eq!(buffer.drain_all_blocking(), Err(OpError::Closed)); // И сразу без ожидания
+    }
 
-    // Многопоточная демонстрация
-    println!("\n=== Многопоточная демонстрация ===");
-    let buffer_clone = Arc::clone(&buffer);
+    #[test]
+    fn test_push_slice_overwrite() {
+        let buffer = SafeRingBuffer::new(4);
+        assert_eq!(buffer.push_slice_overwrite(&[1, 2]), Ok(vec![])); // Место есть
+        assert_eq!(buffer.push_slice_overwrite(&[3, 4, 5]), Ok(vec![1]));
+        assert_eq!(buffer.cursor().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
 
-    // Поток-писатель
-    let writer = thread::spawn(move || {
-        for i in 1..=5 {
-            buffer_clone.push(i).unwrap();
-            println!("Писатель записал: {}", i);
+        // Срез длиннее вместимости: выживают последние 4 байта
+        assert_eq!(
+            buffer.push_slice_overwrite(&[6, 7, 8, 9, 10, 11]),
+            Ok(vec![2, 3, 4, 5, 6, 7])
+        );
+        assert_eq!(drain(&buffer), vec![8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_peek_range() {
+        let buffer = SafeRingBuffer::new(8);
+        buffer.push_slice(&[0; 5]).unwrap();
+        buffer.pop_slice(&mut [0; 5]);
+        buffer.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(); // Голова в слоте 5
+
+        assert_eq!(buffer.peek_range(2, 6), Some(vec![3, 4, 5, 6])); // Через границу
+        assert_eq!(buffer.peek_range(4, 8), Some(vec![5, 6, 7, 8]));
+        assert_eq!(buffer.peek_range(3, 3), Some(vec![]));
+        assert_eq!(buffer.peek_range(4, 9), None);
+        assert_eq!(buffer.peek_range(5, 4), None);
+        assert_eq!(buffer.len(), 8); // Ничего не извлечено
+    }
+
+    #[test]
+    fn test_fold_drain() {
+        let buffer = SafeRingBuffer::new(4);
+        assert_eq!(buffer.fold_drain(7u64, |acc, v| acc + v as u64), 7);
+
+        buffer.push_slice(&[10, 20, 30]).unwrap();
+        assert_eq!(buffer.fold_drain(0u64, |acc, v| acc + v as u64), 60);
+        assert_eq!(buffer.len(), 0);
+
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        let order = buffer.fold_drain(Vec::new(), |mut seen, v| {
+            seen.push(v);
+            seen
+        });
+        assert_eq!(order, vec![1, 2, 3]); // Порядок FIFO
+    }
+
+    #[test]
+    fn test_strict_mode_error() {
+        let buffer = SafeRingBuffer::new(0).with_strict_mode(StrictMode::Error);
+        assert_eq!(buffer.push(1), Err(BufferError::ZeroCapacity));
+        assert_eq!(buffer.push_slice(&[1]), Err(BufferError::ZeroCapacity));
+        assert_eq!(
+            buffer.push_blocking(1),
+            Err(OpError::Buffer(BufferError::ZeroCapacity))
+        );
+
+        // Повторный захват ловится только в debug-сборке
+        #[cfg(debug_assertions)]
+        {
+            let _held = buffer.acquire();
+            assert_eq!(buffer.try_acquire().err(), Some(OpError::Reentrant));
         }
-    });
+    }
 
-    // Поток-читатель
-    let reader = thread::spawn(move || {
-        for _ in 1..=5 {
-            if let Some(val) = buffer.pop() {
-                println!("Читатель прочитал: {}", val);
-            }
+    #[test]
+    fn test_strict_mode_panic() {
+        let buffer = SafeRingBuffer::new(0).with_strict_mode(StrictMode::Panic);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| buffer.push(1)));
+        let message = result.unwrap_err();
+        assert_eq!(
+            message.downcast_ref::<&str>(),
+            Some(&"push into a zero-capacity SafeRingBuffer")
+        );
+    }
+
+    #[test]
+    fn test_notify_threshold() {
+        let buffer = Arc::new(SafeRingBuffer::new(8).with_notify_threshold(4));
+        let spawn_consumer = || {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.drain_all_blocking())
+        };
+
+        let consumer = spawn_consumer();
+        thread::sleep(Duration::from_millis(20));
+        for value in 1..=3 {
+            buffer.push(value).unwrap();
         }
-    });
+        thread::sleep(Duration::from_millis(30));
+        assert!(!consumer.is_finished(), "разбудили до порога");
+        buffer.push(4).unwrap();
+        assert_eq!(consumer.join().unwrap(), Ok(vec![1, 2, 3, 4]));
 
-    // Ждем завершения потоков
-    writer.join().unwrap();
-    reader.join().unwrap();
-}
+        // flush будит и ниже порога
+        let consumer = spawn_consumer();
+        thread::sleep(Duration::from_millis(20));
+        buffer.push(5).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        assert!(!consumer.is_finished());
+        buffer.flush();
+        assert_eq!(consumer.join().unwrap(), Ok(vec![5]));
+    }
 
-// Тесты
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_try_from_capacity_and_vec() {
+        let mut buffer = RingBuffer::try_from((5, vec![1, 2, 3])).unwrap();
+        assert_eq!((buffer.len(), buffer.capacity), (3, 5));
+        assert_eq!(buffer.push_slice(&[4, 5]), Ok(()));
+
+        assert_eq!(
+            RingBuffer::try_from((2, vec![1, 2, 3])).unwrap_err(),
+            BufferError::Full { capacity: 2 }
+        );
+
+        let full = RingBuffer::try_from((3, vec![1, 2, 3])).unwrap();
+        assert!(full.is_full());
+        assert_eq!(full.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
 
     #[test]
-    fn test_single_thread() {
-        let buffer = SafeRingBuffer::new(3);
+    fn test_producer_quota() {
+        let buffer = SafeRingBuffer::new(4).with_producer_quota(0.5);
+        // Каждому из двух производителей — не больше половины буфера
+        assert_eq!(buffer.push_from(1, 10), Ok(()));
+        assert_eq!(buffer.push_from(1, 11), Ok(()));
+        assert_eq!(
+            buffer.push_from(1, 12),
+            Err(BufferError::QuotaExceeded { producer: 1 })
+        );
+        assert_eq!(buffer.len(), 2); // Место есть, но квота исчерпана
+        assert_eq!(buffer.push_from(2, 20), Ok(()));
+        assert_eq!(buffer.push_from(2, 21), Ok(()));
+        assert_eq!(
+            buffer.push_from(2, 22),
+            Err(BufferError::QuotaExceeded { producer: 2 })
+        );
+        // Новому производителю квота позволяет, но буфер полон
+        assert_eq!(
+            buffer.push_from(3, 30),
+            Err(BufferError::Full { capacity: 4 })
+        );
 
-        // Заполняем буфер
-        assert_eq!(buffer.push(1), Ok(()));
-        assert_eq!(buffer.push(2), Ok(()));
-        assert_eq!(buffer.push(3), Ok(()));
-        assert_eq!(buffer.push(4), Err(BufferError::Full)); // Переполнение
+        // Извлечение элемента производителя 1 возвращает ему квоту
+        assert_eq!(buffer.pop(), Some(10));
+        assert_eq!(buffer.push_from(1, 12), Ok(()));
+        assert_eq!(drain(&buffer), vec![11, 20, 21, 12]);
+    }
 
-        // Читаем данные
+    #[test]
+    fn test_producer_quota_tracks_removals() {
+        let buffer = SafeRingBuffer::new(4).with_producer_quota(0.5);
+        let owned = |buffer: &SafeRingBuffer| {
+            let book = buffer.quota.as_ref().unwrap().book.lock().unwrap();
+            (book.owners.len(), book.counts.get(&1).copied().unwrap_or(0))
+        };
+        buffer.push_from(1, 10).unwrap();
+        buffer.push_from(1, 11).unwrap();
+
+        // Квоту возвращает любое извлечение, не только pop с головы
+        assert_eq!(buffer.pop_back(), Some(11));
+        assert_eq!(owned(&buffer), (1, 1));
+        buffer.push_from(1, 12).unwrap();
+        buffer.push_slice(&[1, 2]).unwrap();
+        assert_eq!(buffer.push_overwrite(3), Ok(Some(10))); // Вытеснение
+        assert!(buffer.remove_first(1)); // Из середины
+        assert_eq!(owned(&buffer), (1, 1));
+        buffer.push_from(1, 13).unwrap();
+        assert_eq!(
+            buffer.push_from(1, 14),
+            Err(BufferError::QuotaExceeded { producer: 1 })
+        );
+        buffer.overwrite_with(&[5]).unwrap(); // Очистка
+        assert_eq!(owned(&buffer), (0, 0));
+        buffer.insert_sorted(4).unwrap(); // Перемещения учет не трогают
+        buffer.push_from(1, 15).unwrap();
+        buffer.push_from(1, 16).unwrap();
+        assert_eq!(owned(&buffer), (2, 2));
+        assert_eq!(drain(&buffer), vec![4, 5, 15, 16]);
+        assert_eq!(owned(&buffer), (0, 0));
+    }
+
+    #[test]
+    fn test_push_from_shares_push_bookkeeping() {
+        let buffer = SafeRingBuffer::new(1)
+            .with_producer_quota(1.0)
+            .with_latency_tracking()
+            .with_lossy_drop(Duration::from_secs(60), |_| {});
+        buffer.push_from(1, 10).unwrap();
+        assert!(buffer.push_latency_percentile(1.0) > Duration::ZERO);
+        // Полный буфер в режиме с потерями: значение отброшено без ошибки
+        assert_eq!(buffer.push_from(2, 20), Ok(()));
+        assert_eq!(buffer.dropped_total(), 1);
+        assert_eq!(drain(&buffer), vec![10]);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let base = std::mem::size_of::<RingBuffer>();
+        let slot = std::mem::size_of::<Option<u8>>() + std::mem::size_of::<u64>();
+        assert_eq!(RingBuffer::new(16).memory_usage(), base + 16 * slot);
+        assert_eq!(RingBuffer::new(1024).memory_usage(), base + 1024 * slot);
+
+        // Растет линейно по вместимости, от содержимого не зависит
+        let small = SafeRingBuffer::new(100);
+        let large = SafeRingBuffer::new(200);
+        large.push_slice(&[1, 2, 3]).unwrap();
+        assert_eq!(
+            large.memory_usage() - small.memory_usage(),
+            small.memory_usage() - base
+        );
+    }
+
+    #[test]
+    fn test_len_relaxed() {
+        let buffer = Arc::new(SafeRingBuffer::new(1000));
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        buffer.push(i).unwrap();
+                    }
+                    buffer.pop();
+                })
+            })
+            .collect();
+
+        // Чтение без блокировки: держим мьютекс и убеждаемся, что
+        // len_relaxed сразу возвращается
+        {
+            let guard = buffer.acquire();
+            let seen = buffer.len_relaxed();
+            assert!(seen <= 400);
+            drop(guard);
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        // После затишья копия совпадает с размером под блокировкой
+        assert_eq!(buffer.len(), 396);
+        assert_eq!(buffer.len_relaxed(), buffer.len());
+    }
+
+    #[test]
+    fn test_drain_frames() {
+        let buffer = SafeRingBuffer::new(16);
+        buffer.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        let (frames, rest) = buffer.drain_frames::<4>();
+        assert_eq!(frames, vec![[1, 2, 3, 4], [5, 6, 7, 8]]);
+        assert_eq!(rest, vec![9]);
+        assert!(buffer.is_empty());
+
+        // Кратное N — без остатка
+        buffer.push_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(
+            buffer.drain_frames::<4>(),
+            (vec![[1, 2, 3, 4], [5, 6, 7, 8]], vec![])
+        );
+
+        // Меньше N — все в остаток
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        assert_eq!(buffer.drain_frames::<4>(), (vec![], vec![1, 2, 3]));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_pop_blocking_cancellable() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        let token = CancelToken::new();
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            let token = token.clone();
+            thread::spawn(move || buffer.pop_blocking_cancellable(&token))
+        };
+
+        thread::sleep(Duration::from_millis(30)); // Потребитель уснул на пустом буфере
+        let cancelled_at = Instant::now();
+        token.cancel();
+        assert_eq!(consumer.join().unwrap(), None);
+        assert!(cancelled_at.elapsed() < Duration::from_secs(1));
+        assert!(buffer.cancel_waiters.lock().unwrap().is_empty());
+
+        // Без отмены данные доходят как обычно
+        let token = CancelToken::new();
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            let token = token.clone();
+            thread::spawn(move || buffer.pop_blocking_cancellable(&token))
+        };
+        thread::sleep(Duration::from_millis(30));
+        buffer.push(7).unwrap();
+        assert_eq!(consumer.join().unwrap(), Some(7));
+    }
+
+    #[test]
+    fn test_push_slice_reporting() {
+        let buffer = SafeRingBuffer::new(4);
+        assert_eq!(buffer.push_slice_reporting(&[1, 2]), Ok(()));
+
+        let values = [3, 4, 5, 6];
+        let Err(partial) = buffer.push_slice_reporting(&values) else {
+            panic!("в буфере место только на два значения");
+        };
+        assert_eq!(partial, PartialPush { accepted: 2 });
+        assert_eq!(buffer.len(), 4); // Принятая часть осталась в буфере
+
+        // Освобождаем место и досылаем остаток
         assert_eq!(buffer.pop(), Some(1));
         assert_eq!(buffer.pop(), Some(2));
-        assert_eq!(buffer.push(4), Ok(())); // Теперь можно записать
-        assert_eq!(buffer.pop(), Some(3));
-        assert_eq!(buffer.pop(), Some(4));
-        assert_eq!(buffer.pop(), None); // Буфер пуст
+        assert_eq!(
+            buffer.push_slice_reporting(&values[partial.accepted..]),
+            Ok(())
+        );
+        assert_eq!(drain(&buffer), vec![3, 4, 5, 6]);
     }
 
     #[test]
-    fn test_multi_thread() {
-        let buffer = Arc::new(SafeRingBuffer::new(100));
-        let mut handles = vec![];
+    fn test_lock_guard_scope() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
 
-        // Запускаем 5 писателей
-        for i in 0..5 {
+        // "Посмотреть и извлечь, если подходит" под одной блокировкой
+        {
+            let mut guard = buffer.lock().unwrap();
+            while guard.peek().is_some_and(|value| value < 3) {
+                guard.pop();
+            }
+            guard.push(4).unwrap();
+        }
+
+        // Guard освобожден: повторная блокировка из того же потока проходит
+        let guard = buffer.lock().unwrap();
+        assert_eq!(guard.iter().collect::<Vec<_>>(), vec![3, 4]);
+        drop(guard);
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    fn test_push_unique_by() {
+        // Старшая тетрада — идентификатор, младшая — полезная нагрузка
+        let id = |value: &u8| value >> 4;
+        let buffer = SafeRingBuffer::new(4);
+        assert_eq!(buffer.push_unique_by(0x11, id), Ok(true));
+        assert_eq!(buffer.push_unique_by(0x1F, id), Ok(false)); // Тот же id, другая нагрузка
+        assert_eq!(buffer.push_unique_by(0x21, id), Ok(true)); // Новый id
+        assert_eq!(drain(&buffer), vec![0x11, 0x21]);
+
+        // После извлечения id снова свободен
+        assert_eq!(buffer.push_unique_by(0x1F, id), Ok(true));
+    }
+
+    #[test]
+    fn test_adaptive_spin_direction() {
+        let spin = AdaptiveSpin::new();
+        // Низкая конкуренция: захват во время серии — серия растет до верхней границы
+        for _ in 0..20 {
+            spin.record(true);
+        }
+        assert_eq!(spin.current(), MAX_SPINS);
+        // Высокая конкуренция: серии проваливаются — падает до нижней
+        for _ in 0..20 {
+            spin.record(false);
+        }
+        assert_eq!(spin.current(), MIN_SPINS);
+    }
+
+    #[test]
+    fn test_adaptive_spin_under_contention() {
+        let buffer = Arc::new(SafeRingBuffer::new(4).with_adaptive_spin());
+        assert_eq!(buffer.current_spin_count(), INITIAL_SPINS);
+
+        // Один поток: конкуренции нет, серия не меняется
+        for i in 0..100 {
+            buffer.push_overwrite(i).unwrap();
+        }
+        assert_eq!(buffer.current_spin_count(), INITIAL_SPINS);
+
+        // Блокировку держат долго: короткие серии не помогают, и после
+        // нескольких таких захватов серия уменьшается
+        for _ in 0..3 {
+            let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+            let holder = {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || {
+                    let _guard = buffer.acquire();
+                    locked_tx.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(20));
+                })
+            };
+            locked_rx.recv().unwrap();
+            buffer.pop();
+            holder.join().unwrap();
+        }
+        assert!(buffer.current_spin_count() < INITIAL_SPINS);
+    }
+
+    #[test]
+    fn test_reservoir_sample() {
+        let run = |seed| {
+            let buffer = SafeRingBuffer::new(4).with_reservoir(5, seed);
+            for i in 0..1000 {
+                buffer.push((i % 100) as u8).unwrap();
+                buffer.pop();
+            }
+            buffer.sample()
+        };
+
+        let sample = run(42);
+        assert_eq!(sample.len(), 5);
+        assert!(sample.iter().all(|&value| value < 100)); // Только записанные значения
+        assert_ne!(sample, vec![0, 1, 2, 3, 4]); // Не просто первые k записей
+        assert_eq!(run(42), sample); // Детерминировано при том же seed
+
+        assert!(SafeRingBuffer::new(4).sample().is_empty());
+
+        // Резервуар видит записи любыми методами, а не только push
+        let buffer = SafeRingBuffer::new(8).with_reservoir(16, 7);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.push_blocking(4).unwrap();
+        buffer.push_overwrite(5).unwrap();
+        buffer.try_push(6).unwrap();
+        broadcast(7, &[&buffer]).unwrap();
+        buffer.begin_push(8).unwrap().commit().unwrap();
+        let mut sample = buffer.sample();
+        sample.sort_unstable();
+        assert_eq!(sample, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Отклоненная запись в выборку не попадает
+        assert!(buffer.push_slice(&[9]).is_err()); // Буфер полон
+        assert_eq!(buffer.sample().len(), 8);
+    }
+
+    #[test]
+    fn test_remove_first() {
+        let buffer = SafeRingBuffer::new(8);
+        buffer.push_slice(&[1, 2, 3, 2, 4]).unwrap();
+
+        assert!(buffer.remove_first(2)); // Только первое вхождение
+        assert!(!buffer.remove_first(9)); // Нет такого — буфер не меняется
+        assert_eq!(buffer.snapshot_versioned().1, vec![1, 3, 2, 4]);
+
+        // Голова — как pop
+        assert!(buffer.remove_first(1));
+        assert_eq!(drain(&buffer), vec![3, 2, 4]);
+    }
+
+    #[test]
+    fn test_spawn_flusher() {
+        use std::sync::mpsc;
+
+        // Размер: пакет уходит задолго до interval
+        let buffer = Arc::new(SafeRingBuffer::new(16));
+        let (tx, rx) = mpsc::channel();
+        let flusher = Arc::clone(&buffer).spawn_flusher(4, Duration::from_secs(60), move |batch| {
+            tx.send(batch).unwrap();
+        });
+        buffer.push_slice(&[1, 2, 3, 4, 5]).unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout), Ok(vec![1, 2, 3, 4]));
+        // Неполный пакет ждет таймера; close дописывает остаток и завершает поток
+        buffer.close();
+        assert_eq!(rx.recv_timeout(timeout), Ok(vec![5]));
+        flusher.join().unwrap();
+        assert!(rx.recv().is_err()); // Поток вышел, отправитель закрыт
+
+        // Время: неполный пакет уходит по истечении interval
+        let buffer = Arc::new(SafeRingBuffer::new(16));
+        let (tx, rx) = mpsc::channel();
+        let flusher =
+            Arc::clone(&buffer).spawn_flusher(4, Duration::from_millis(20), move |batch| {
+                tx.send(batch).unwrap();
+            });
+        buffer.push_slice(&[6, 7]).unwrap();
+        assert_eq!(rx.recv_timeout(timeout), Ok(vec![6, 7]));
+        buffer.close();
+        flusher.join().unwrap();
+        assert!(rx.recv().is_err());
+
+        // Duration::MAX: таймера нет, пакеты уходят по размеру и при close
+        let buffer = Arc::new(SafeRingBuffer::new(16));
+        let (tx, rx) = mpsc::channel();
+        let flusher = Arc::clone(&buffer).spawn_flusher(2, Duration::MAX, move |batch| {
+            tx.send(batch).unwrap();
+        });
+        buffer.push_slice(&[8, 9, 10]).unwrap();
+        assert_eq!(rx.recv_timeout(timeout), Ok(vec![8, 9]));
+        buffer.close();
+        assert_eq!(rx.recv_timeout(timeout), Ok(vec![10]));
+        flusher.join().unwrap();
+    }
+
+    #[test]
+    fn test_retain_count() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(buffer.retain_count(|&value| value % 2 == 0), 2);
+        assert_eq!(buffer.snapshot_versioned().1, vec![2, 4]);
+
+        assert_eq!(buffer.retain_count(|_| true), 0); // Все остаются
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.retain_count(|_| false), 2); // Никто не остается
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_cow_snapshot() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2]).unwrap();
+
+        let first = buffer.cow_snapshot();
+        let second = buffer.cow_snapshot();
+        assert!(Arc::ptr_eq(&first, &second)); // Без изменений — та же копия
+
+        buffer.push(3).unwrap();
+        let third = buffer.cow_snapshot();
+        assert!(!Arc::ptr_eq(&first, &third));
+        assert_eq!(third.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(first.iter().collect::<Vec<_>>(), vec![1, 2]); // Старый снимок не меняется
+    }
+
+    // Статические проверки потокобезопасности: тест не собирается, если тип
+    // перестал быть Send/Sync (например, после добавления Rc, Cell или сырого
+    // указателя без unsafe impl).
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    // Намеренно не Send (держат MutexGuard, который отпускается только в
+    // захватившем потоке): LockGuard/BufferGuard, PeekGuard, PushTicket.
+    // Их проверить статически нельзя без сторонних крейтов — только описать.
+    #[test]
+    fn test_thread_safety_markers() {
+        assert_send_sync::<RingBuffer>();
+        assert_send_sync::<ArrayRingBuffer<8>>();
+        assert_send_sync::<SafeRingBuffer>();
+        assert_send_sync::<Arc<SafeRingBuffer>>();
+        assert_send_sync::<CancelToken>();
+        assert_send_sync::<Cursor>();
+        assert_send_sync::<BufferError>();
+        assert_send_sync::<OpError>();
+        assert_send_sync::<crate::ack::AckRingBuffer>();
+        assert_send_sync::<crate::consumer_group::ConsumerGroup>();
+        assert_send_sync::<crate::delay::DelayRingBuffer>();
+        assert_send_sync::<crate::frame::FrameProducer>();
+        assert_send_sync::<crate::priority::BoundedPriorityQueue<u8>>();
+        assert_send_sync::<crate::rate_limited::RateLimitedConsumer>();
+        assert_send_sync::<crate::receiver::Receiver>();
+        assert_send_sync::<crate::scheduler::WeightedScheduler>();
+        assert_send_sync::<crate::semaphore::Semaphore>();
+        assert_send_sync::<crate::spill::SpillRingBuffer>();
+        #[cfg(feature = "async")]
+        assert_send_sync::<crate::async_buffer::AsyncRingBuffer>();
+        #[cfg(feature = "bytes")]
+        {
+            assert_send_sync::<crate::bytes_io::RingBuf<'static>>();
+            assert_send_sync::<crate::bytes_io::RingBufMut<'static>>();
+        }
+    }
+
+    #[test]
+    fn test_push_blocking_then_overwrite() {
+        // Потребитель успевает освободить место — обычная запись
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        buffer.push_slice(&[1, 2]).unwrap();
+        let consumer = {
             let buffer = Arc::clone(&buffer);
-            handles.push(thread::spawn(move || {
-                for j in 1..=10 {
-                    if let Err(e) = buffer.push(i * 20 + j) {
-                        println!("Ошибка записи: {:?}", e);
-                        break;
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                buffer.pop()
+            })
+        };
+        assert_eq!(
+            buffer.push_blocking_then_overwrite(3, Duration::from_secs(5)),
+            Ok(false)
+        );
+        assert_eq!(consumer.join().unwrap(), Some(1));
+        assert_eq!(drain(&buffer), vec![2, 3]);
+
+        // Буфер так и не освободился — после grace вытесняется старейший
+        buffer.push_slice(&[1, 2]).unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            buffer.push_blocking_then_overwrite(3, Duration::from_millis(30)),
+            Ok(true)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(drain(&buffer), vec![2, 3]);
+
+        // Duration::MAX — без вытеснения, ждем места как push_blocking
+        buffer.push_slice(&[1, 2]).unwrap();
+        let consumer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                buffer.pop()
+            })
+        };
+        assert_eq!(
+            buffer.push_blocking_then_overwrite(3, Duration::MAX),
+            Ok(false)
+        );
+        assert_eq!(consumer.join().unwrap(), Some(1));
+        assert_eq!(drain(&buffer), vec![2, 3]);
+
+        // Все слоты под резервом: вытеснять нечего, значение не записано
+        let reserved = SafeRingBuffer::new(1);
+        let ticket = reserved.begin_push(1).unwrap();
+        assert_eq!(
+            reserved.push_blocking_then_overwrite(3, Duration::from_millis(10)),
+            Err(OpError::Buffer(BufferError::Full { capacity: 1 }))
+        );
+        drop(ticket);
+        assert!(reserved.is_empty());
+    }
+
+    #[test]
+    fn test_drain_n_with() {
+        let buffer = SafeRingBuffer::new(8);
+        buffer.push_slice(&[1, 2, 3, 4, 5]).unwrap();
+        let mut seen = Vec::new();
+        assert_eq!(buffer.drain_n_with(3, |value| seen.push(value)), 3);
+        assert_eq!(seen, vec![1, 2, 3]);
+        assert_eq!(buffer.len(), 2);
+
+        assert_eq!(buffer.drain_n_with(10, |_| {}), 2); // Сколько было, не больше
+        let mut calls = 0;
+        assert_eq!(buffer.drain_n_with(3, |_| calls += 1), 0);
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn test_cycle_snapshot() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        let cycle = buffer.cycle_snapshot();
+        buffer.pop(); // Снимок от изменений не зависит
+        assert_eq!(cycle.take(7).collect::<Vec<_>>(), vec![1, 2, 3, 1, 2, 3, 1]);
+
+        assert_eq!(SafeRingBuffer::new(4).cycle_snapshot().next(), None);
+    }
+
+    #[test]
+    fn test_assert_drains_to() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.assert_drains_to(&[1, 2, 3]);
+        assert!(buffer.is_empty());
+
+        buffer.push_slice(&[1, 3, 2]).unwrap();
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            buffer.assert_drains_to(&[1, 2, 3]);
+        }))
+        .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("expected: [1, 2, 3]"), "{message}");
+        assert!(message.contains("actual: [1, 3, 2]"), "{message}");
+        assert!(message.contains("index 1"), "{message}");
+    }
+
+    #[test]
+    fn test_on_watermark() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let buffer = {
+            let seen = Arc::clone(&seen);
+            SafeRingBuffer::new(8).on_watermark(2, 5, move |mark| seen.lock().unwrap().push(mark))
+        };
+        let take = || std::mem::take(&mut *seen.lock().unwrap());
+
+        // Выше high — один раз, сколько бы ни дописывали сверху
+        buffer.push_slice(&[0; 5]).unwrap();
+        assert_eq!(take(), vec![]);
+        buffer.push(0).unwrap();
+        buffer.push(0).unwrap();
+        assert_eq!(take(), vec![Watermark::High]);
+
+        // Внутри полосы — тишина; ниже low — один раз
+        for _ in 0..5 {
+            buffer.pop();
+        }
+        assert_eq!(take(), vec![]);
+        buffer.pop();
+        buffer.pop();
+        assert_eq!(take(), vec![Watermark::Low]);
+
+        // Каждое новое пересечение срабатывает снова
+        buffer.push_slice(&[0; 6]).unwrap();
+        buffer.drain_n_with(6, |_| {});
+        buffer.push_slice(&[0; 6]).unwrap();
+        assert_eq!(
+            take(),
+            vec![Watermark::High, Watermark::Low, Watermark::High]
+        );
+    }
+
+    #[test]
+    fn test_multi_thread_exact_values() {
+        // Писатели с непересекающимися диапазонами и читатели работают
+        // одновременно через маленький буфер: проверяем не только количество,
+        // но и что каждое значение прочитано ровно один раз
+        const WRITERS: u8 = 4;
+        const PER_WRITER: u8 = 60;
+        let buffer = Arc::new(SafeRingBuffer::new(8));
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || {
+                    for j in 0..PER_WRITER {
+                        buffer.push_blocking(w * PER_WRITER + j).unwrap();
                     }
-                }
-            }));
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || {
+                    std::iter::from_fn(|| buffer.pop_blocking().ok()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
         }
+        buffer.close();
+        let per_reader: Vec<Vec<u8>> = readers.into_iter().map(|r| r.join().unwrap()).collect();
 
-        // Ждем завершения писателей
-        for handle in handles.drain(..) {
-            handle.join().unwrap();
+        // Внутри одного читателя значения каждого писателя идут по возрастанию (FIFO)
+        for popped in &per_reader {
+            for w in 0..WRITERS {
+                let range = w * PER_WRITER..(w + 1) * PER_WRITER;
+                let from_writer: Vec<_> = popped.iter().filter(|v| range.contains(v)).collect();
+                assert!(from_writer.is_sorted(), "нарушен FIFO: {from_writer:?}");
+            }
         }
+        let mut all: Vec<u8> = per_reader.into_iter().flatten().collect();
+        all.sort_unstable();
+        let expected: Vec<u8> = (0..WRITERS * PER_WRITER).collect();
+        assert_eq!(all, expected); // Без потерь и без дубликатов
+    }
 
-        // Запускаем 5 читателей
-        let results = Arc::new(Mutex::new(Vec::new()));
-        for _ in 0..5 {
+    #[test]
+    fn test_pop_timeout() {
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let start = Instant::now();
+        assert_eq!(
+            buffer.pop_timeout(Duration::from_millis(20)),
+            Err(OpError::LockTimeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let writer = {
             let buffer = Arc::clone(&buffer);
-            let results = Arc::clone(&results);
-            handles.push(thread::spawn(move || {
-                for _ in 1..=10 {
-                    if let Some(val) = buffer.pop() {
-                        results.lock().unwrap().push(val);
-                    }
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                buffer.push(5).unwrap();
+            })
+        };
+        assert_eq!(buffer.pop_timeout(Duration::from_secs(5)), Ok(5));
+        writer.join().unwrap();
+
+        // Непредставимый срок — ожидание без ограничения, а не паника
+        buffer.push(6).unwrap();
+        assert_eq!(buffer.pop_timeout(Duration::MAX), Ok(6));
+    }
+
+    #[test]
+    fn test_with_front_n() {
+        // Старшая тетрада — "поле" элемента; буфер с переходом через границу
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[0x10, 0x20, 0x30]).unwrap();
+        buffer.pop();
+        buffer.pop();
+        buffer.push_slice(&[0x41, 0x52, 0x63]).unwrap(); // Отрезки [0x30, 0x41] и [0x52, 0x63]
+
+        let field_sum = |n| {
+            buffer.with_front_n(n, |first, second| {
+                first
+                    .iter()
+                    .chain(second)
+                    .flatten()
+                    .map(|value| (value >> 4) as u32)
+                    .sum::<u32>()
+            })
+        };
+        assert_eq!(field_sum(3), 3 + 4 + 5); // Через границу массива
+        assert_eq!(field_sum(1), 3);
+        assert_eq!(field_sum(10), 3 + 4 + 5 + 6); // Не больше, чем есть
+
+        let lens = buffer.with_front_n(3, |first, second| (first.len(), second.len()));
+        assert_eq!(lens.0 + lens.1, 3);
+        assert!(lens.1 > 0);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut buffer = RingBuffer::new(4);
+        buffer.resize(64).unwrap();
+        assert_eq!(buffer.allocated_slots(), 64); // Рост выделяет точно
+
+        // Содержимое с переходом через границу переживает уменьшение на месте
+        buffer.push_slice(&[0; 60]).unwrap();
+        for _ in 0..60 {
+            buffer.pop();
+        }
+        buffer.push_slice(&[5, 6, 7, 8, 9, 10]).unwrap();
+        buffer.resize(8).unwrap();
+        assert_eq!(buffer.allocated_slots(), 64); // Память пока не отдана
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), vec![5, 6, 7, 8, 9, 10]);
+        buffer.push_slice(&[11, 12]).unwrap();
+        assert_eq!(buffer.push(13), Err(BufferError::Full { capacity: 8 }));
+        let history = buffer.capacity_history.len();
+
+        buffer.shrink_to_fit();
+        assert_eq!(buffer.allocated_slots(), 8);
+        assert_eq!((buffer.capacity, buffer.head), (8, 0));
+        assert_eq!(
+            buffer.iter().collect::<Vec<_>>(),
+            vec![5, 6, 7, 8, 9, 10, 11, 12]
+        );
+        assert_eq!(buffer.capacity_history.len(), history);
+        buffer.shrink_to_fit(); // Лишнего больше нет — ничего не меняется
+        assert_eq!(buffer.allocated_slots(), 8);
+
+        let safe = SafeRingBuffer::new(16);
+        safe.push_slice(&[1, 2]).unwrap();
+        safe.resize(4).unwrap();
+        safe.shrink_to_fit();
+        assert_eq!(safe.transaction(|ring| ring.allocated_slots()), 4);
+        assert_eq!(drain(&safe), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        use yield_hook::set_yield_hook;
+
+        let buffer = SafeRingBuffer::new(4).with_latency_tracking();
+        let slow = Arc::new(AtomicBool::new(false));
+        {
+            let slow = Arc::clone(&slow);
+            set_yield_hook(Some(Box::new(move |point| {
+                if point == "push" && slow.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(5));
                 }
-            }));
+            })));
+        }
+        // 98 быстрых и 2 медленных записи
+        for i in 0..100 {
+            slow.store(i >= 98, Ordering::Relaxed);
+            buffer.push(1).unwrap();
+            buffer.pop();
         }
+        set_yield_hook(None);
 
-        // Ждем завершения читателей
-        for handle in handles {
-            handle.join().unwrap();
+        assert!(buffer.push_latency_percentile(0.5) < Duration::from_millis(1));
+        assert!(buffer.push_latency_percentile(0.99) >= Duration::from_millis(5));
+        assert!(buffer.pop_latency_percentile(0.99) < Duration::from_millis(5));
+
+        // Без with_latency_tracking гистограмм нет
+        let untracked = SafeRingBuffer::new(4);
+        untracked.push(1).unwrap();
+        assert!(untracked.latency.is_none());
+        assert_eq!(untracked.push_latency_percentile(0.99), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_clear_poison() {
+        let poison = |buffer: &Arc<SafeRingBuffer>, f: fn(&mut RingBuffer)| {
+            let buffer = Arc::clone(buffer);
+            let poisoner = thread::spawn(move || {
+                buffer.transaction(|ring| {
+                    f(ring);
+                    panic!("отравляем мьютекс");
+                })
+            });
+            assert!(poisoner.join().is_err());
+        };
+
+        // Паника между операциями: данные согласованы и сохраняются
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push(1).unwrap();
+        poison(&buffer, |ring| ring.push(2).unwrap());
+        assert_eq!(buffer.try_push(3), Err(OpError::Poisoned));
+        let recovered = buffer.clear_poison();
+        assert!(recovered.is_consistent());
+        assert_eq!(recovered.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(buffer.try_push(3), Ok(()));
+        assert_eq!(drain(&buffer), vec![1, 2, 3]);
+
+        // Паника посреди изменения: инварианты нарушены — буфер очищается
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push(1).unwrap();
+        poison(&buffer, |ring| ring.size = 3);
+        let recovered = buffer.clear_poison();
+        assert!(recovered.is_empty() && recovered.is_consistent());
+        buffer.push_slice(&[4, 5]).unwrap();
+        assert_eq!(drain(&buffer), vec![4, 5]);
+        assert_eq!(buffer.len_relaxed(), 0);
+
+        // Снимок, взятый до отравления, не переживает очистку при восстановлении
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push_slice(&[1, 2]).unwrap();
+        let before = buffer.cow_snapshot();
+        poison(&buffer, |ring| ring.size = 3);
+        buffer.clear_poison();
+        let after = buffer.cow_snapshot();
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert!(after.is_empty());
+        assert_eq!(before.iter().collect::<Vec<_>>(), vec![1, 2]);
+
+        // Живой резерв переживает очистку: commit после восстановления проходит
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push(1).unwrap();
+        let ticket = buffer.begin_push(9).unwrap();
+        poison(&buffer, |ring| ring.size = 4);
+        let recovered = buffer.clear_poison();
+        assert!(recovered.is_empty() && recovered.is_consistent());
+        ticket.commit().unwrap();
+        assert_eq!(drain(&buffer), vec![9]);
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let buffer = SafeRingBuffer::new(4);
+        buffer.push_slice(&[1, 2, 3]).unwrap();
+        buffer.pop();
+        buffer.pop();
+        buffer.push_slice(&[4, 5]).unwrap(); // Физически: [5, _, 3, 4]
+
+        let dump = buffer.debug_dump();
+        for line in [
+            "  capacity: 4\n",
+            "  size: 3\n",
+            "  head: 2\n",
+            "  tail: 1\n",
+            "    [0] Some(5)\n",
+            "    [1] None <- tail\n",
+            "    [2] Some(3) <- head\n",
+            "    [3] Some(4)\n",
+            "  contents: [3, 4, 5]\n",
+        ] {
+            assert!(dump.contains(line), "нет {line:?} в:\n{dump}");
         }
 
-        // Проверяем, что все данные прочитаны
-        let results = results.lock().unwrap();
-        assert_eq!(results.len(), 50);
+        // Полный буфер: head и tail совпадают
+        let full = RingBuffer::try_from((2, vec![7, 8])).unwrap();
+        assert!(
+            full.debug_dump()
+                .contains("    [0] Some(7) <- head, tail\n")
+        );
     }
 }
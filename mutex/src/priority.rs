@@ -0,0 +1,208 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Condvar, Mutex};
+
+use crate::BufferError;
+
+// Порядок извлечения из очереди с приоритетом
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriorityOrder {
+    Max, // Сначала наибольший
+    Min, // Сначала наименьший
+}
+
+// Класс записи для push_lane: у каждого своя реакция на переполнение
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lane {
+    High,   // Критичные записи
+    Normal, // Обычные записи
+}
+
+// Что делает push_lane, когда очередь полна
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    Block, // Ждать освобождения места (значение не теряется)
+    Drop,  // Отбросить значение
+}
+
+// Куча в нужном порядке
+#[derive(Debug)]
+enum Heap<T: Ord> {
+    Max(BinaryHeap<T>),
+    Min(BinaryHeap<Reverse<T>>),
+}
+
+// Ограниченная потокобезопасная очередь с приоритетом поверх BinaryHeap.
+// Семантика вместимости та же, что у SafeRingBuffer: при заполнении push
+// возвращает BufferError::Full, пустая очередь — BufferError::Empty.
+#[derive(Debug)]
+pub struct BoundedPriorityQueue<T: Ord> {
+    heap: Mutex<Heap<T>>,
+    capacity: usize,
+    not_full: Condvar,      // Место освободилось (для OverflowPolicy::Block)
+    high: OverflowPolicy,   // Переполнение для Lane::High
+    normal: OverflowPolicy, // Переполнение для Lane::Normal
+}
+
+impl<T: Ord> BoundedPriorityQueue<T> {
+    // Очередь, выдающая сначала наибольший элемент
+    pub fn new(capacity: usize) -> Self {
+        Self::with_order(capacity, PriorityOrder::Max)
+    }
+
+    pub fn with_order(capacity: usize, order: PriorityOrder) -> Self {
+        let heap = match order {
+            PriorityOrder::Max => Heap::Max(BinaryHeap::with_capacity(capacity)),
+            PriorityOrder::Min => Heap::Min(BinaryHeap::with_capacity(capacity)),
+        };
+        BoundedPriorityQueue {
+            heap: Mutex::new(heap),
+            capacity,
+            not_full: Condvar::new(),
+            high: OverflowPolicy::Drop,
+            normal: OverflowPolicy::Drop,
+        }
+    }
+
+    // Реакция push_lane на переполнение для lane (по умолчанию Drop для
+    // обеих). Например, High — Block, Normal — Drop: критичные записи ждут
+    // места, обычные при заполнении теряются.
+    pub fn with_lane_policy(mut self, lane: Lane, policy: OverflowPolicy) -> Self {
+        match lane {
+            Lane::High => self.high = policy,
+            Lane::Normal => self.normal = policy,
+        }
+        self
+    }
+
+    // Запись с политикой переполнения своей полосы; false — значение
+    // отброшено (Drop). С Block ждет, пока pop не освободит место. Порядок
+    // извлечения по-прежнему задает только Ord значения, а не полоса.
+    pub fn push_lane(&self, lane: Lane, value: T) -> bool {
+        let policy = match lane {
+            Lane::High => self.high,
+            Lane::Normal => self.normal,
+        };
+        let mut heap = self.heap.lock().unwrap();
+        while heap.len() >= self.capacity {
+            if policy == OverflowPolicy::Drop || self.capacity == 0 {
+                return false; // Нулевую вместимость не дождаться
+            }
+            heap = self.not_full.wait(heap).unwrap();
+        }
+        match &mut *heap {
+            Heap::Max(h) => h.push(value),
+            Heap::Min(h) => h.push(Reverse(value)),
+        }
+        true
+    }
+
+    pub fn push(&self, value: T) -> Result<(), BufferError> {
+        let mut heap = self.heap.lock().unwrap();
+        if heap.len() >= self.capacity {
+            return Err(BufferError::Full {
+                capacity: self.capacity,
+            });
+        }
+        match &mut *heap {
+            Heap::Max(h) => h.push(value),
+            Heap::Min(h) => h.push(Reverse(value)),
+        }
+        Ok(())
+    }
+
+    // Извлечение элемента с наивысшим приоритетом
+    pub fn pop(&self) -> Result<T, BufferError> {
+        let mut heap = self.heap.lock().unwrap();
+        let value = match &mut *heap {
+            Heap::Max(h) => h.pop(),
+            Heap::Min(h) => h.pop().map(|Reverse(v)| v),
+        };
+        if value.is_some() {
+            self.not_full.notify_all();
+        }
+        value.ok_or(BufferError::Empty)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Ord> Heap<T> {
+    fn len(&self) -> usize {
+        match self {
+            Heap::Max(h) => h.len(),
+            Heap::Min(h) => h.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<T: Ord>(queue: &BoundedPriorityQueue<T>) -> Vec<T> {
+        std::iter::from_fn(|| queue.pop().ok()).collect()
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let queue = BoundedPriorityQueue::new(5);
+        for value in [3, 1, 4, 1, 5] {
+            queue.push(value).unwrap();
+        }
+        assert_eq!(drain(&queue), vec![5, 4, 3, 1, 1]);
+        assert_eq!(queue.pop(), Err(BufferError::Empty));
+    }
+
+    #[test]
+    fn test_priority_overflow() {
+        let queue = BoundedPriorityQueue::new(2);
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        assert_eq!(queue.push(3), Err(BufferError::Full { capacity: 2 }));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_priority_min_order() {
+        let queue = BoundedPriorityQueue::with_order(5, PriorityOrder::Min);
+        for value in [3, 1, 4, 1, 5] {
+            queue.push(value).unwrap();
+        }
+        assert_eq!(drain(&queue), vec![1, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_lane_overflow_policies() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let queue = Arc::new(
+            BoundedPriorityQueue::new(2)
+                .with_lane_policy(Lane::High, OverflowPolicy::Block)
+                .with_lane_policy(Lane::Normal, OverflowPolicy::Drop),
+        );
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+
+        assert!(!queue.push_lane(Lane::Normal, 3)); // Полно — обычная запись теряется
+        assert_eq!(queue.len(), 2);
+
+        let producer = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || queue.push_lane(Lane::High, 9))
+        };
+        thread::sleep(Duration::from_millis(20)); // Производитель ждет места
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop(), Ok(2));
+        assert!(producer.join().unwrap());
+        assert_eq!(drain(&queue), vec![9, 1]);
+    }
+}
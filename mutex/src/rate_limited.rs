@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::SafeRingBuffer;
+
+// Потребитель с ограничением темпа: успешные pop разнесены по времени не
+// меньше чем на min_interval (например, под квоту внешнего API)
+#[derive(Debug)]
+pub struct RateLimitedConsumer {
+    buffer: Arc<SafeRingBuffer>,
+    min_interval: Duration,
+    last_pop: Mutex<Option<Instant>>, // Момент последнего успешного pop
+}
+
+impl RateLimitedConsumer {
+    pub fn new(buffer: Arc<SafeRingBuffer>, min_interval: Duration) -> Self {
+        RateLimitedConsumer {
+            buffer,
+            min_interval,
+            last_pop: Mutex::new(None),
+        }
+    }
+
+    // Извлечение с выдержкой интервала. Пустой буфер сразу дает None:
+    // ожидания нет, и отсчет интервала не начинается. Несколько потоков
+    // выдерживают интервал по очереди.
+    pub fn pop(&self) -> Option<u8> {
+        let mut last_pop = self.last_pop.lock().expect("мьютекс темпа отравлен");
+        if self.buffer.is_empty() {
+            return None;
+        }
+        if let Some(last) = *last_pop {
+            let elapsed = last.elapsed();
+            if elapsed < self.min_interval {
+                thread::sleep(self.min_interval - elapsed);
+            }
+        }
+        let value = self.buffer.pop()?;
+        *last_pop = Some(Instant::now());
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pops_are_spaced() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        buffer.push_slice(&[1, 2]).unwrap();
+        let consumer = RateLimitedConsumer::new(buffer, Duration::from_millis(30));
+
+        let start = Instant::now();
+        assert_eq!(consumer.pop(), Some(1)); // Первый — без ожидания
+        let first = start.elapsed();
+        assert_eq!(consumer.pop(), Some(2));
+        assert!(start.elapsed() - first >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_empty_pop_does_not_start_clock() {
+        let buffer = Arc::new(SafeRingBuffer::new(4));
+        let consumer = RateLimitedConsumer::new(Arc::clone(&buffer), Duration::from_secs(60));
+
+        assert_eq!(consumer.pop(), None);
+        buffer.push(1).unwrap();
+        let start = Instant::now();
+        assert_eq!(consumer.pop(), Some(1)); // Интервал не отсчитывался от пустого pop
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
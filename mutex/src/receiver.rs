@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::sync::mpsc::{RecvError, TryRecvError};
+
+use crate::SafeRingBuffer;
+
+// Адаптер с API получателя std::sync::mpsc поверх кольцевого буфера,
+// чтобы буфер можно было подставить туда, где ожидается Receiver.
+// "Отключение" отправителей соответствует закрытию буфера (close).
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    buffer: Arc<SafeRingBuffer>,
+}
+
+impl Receiver {
+    pub fn new(buffer: Arc<SafeRingBuffer>) -> Self {
+        Receiver { buffer }
+    }
+
+    // Блокирующее получение; RecvError — буфер закрыт и пуст
+    pub fn recv(&self) -> Result<u8, RecvError> {
+        self.buffer.pop_blocking().map_err(|_| RecvError)
+    }
+
+    // Неблокирующее получение: Empty — данных пока нет,
+    // Disconnected — буфер закрыт и пуст
+    pub fn try_recv(&self) -> Result<u8, TryRecvError> {
+        // Проверка закрытия под той же блокировкой, что и извлечение
+        self.buffer.transaction(|buffer| match buffer.pop() {
+            Some(value) => Ok(value),
+            None if self.buffer.is_closed() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_try_recv() {
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let receiver = Receiver::new(Arc::clone(&buffer));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        buffer.push(5).unwrap();
+        buffer.close();
+        assert_eq!(receiver.try_recv(), Ok(5)); // Закрытый буфер дочитывается
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_recv_blocks_until_push() {
+        let buffer = Arc::new(SafeRingBuffer::new(2));
+        let receiver = Receiver::new(Arc::clone(&buffer));
+        let handle = thread::spawn(move || receiver.recv());
+
+        thread::sleep(Duration::from_millis(10));
+        assert!(!handle.is_finished());
+        buffer.push(7).unwrap();
+        assert_eq!(handle.join().unwrap(), Ok(7));
+    }
+}
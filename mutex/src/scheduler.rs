@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use crate::SafeRingBuffer;
+
+// Взвешенный циклический опрос нескольких буферов: буфер с весом w отдает
+// до w элементов подряд, затем очередь переходит к следующему. Пустые
+// буферы пропускаются; буфер с нулевым весом не опрашивается вовсе.
+#[derive(Debug)]
+pub struct WeightedScheduler {
+    buffers: Vec<(Arc<SafeRingBuffer>, u32)>,
+    current: usize, // Чей сейчас ход
+    credit: u32,    // Сколько еще элементов можно взять у текущего
+}
+
+impl WeightedScheduler {
+    pub fn new(buffers: Vec<(Arc<SafeRingBuffer>, u32)>) -> Self {
+        let credit = buffers.first().map_or(0, |&(_, weight)| weight);
+        WeightedScheduler {
+            buffers,
+            current: 0,
+            credit,
+        }
+    }
+
+    // Ход переходит к следующему буферу с полным запасом по его весу
+    fn rotate(&mut self) {
+        self.current = (self.current + 1) % self.buffers.len();
+        self.credit = self.buffers[self.current].1;
+    }
+}
+
+// None — все буферы пусты (на момент опроса)
+impl Iterator for WeightedScheduler {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.buffers.is_empty() {
+            return None;
+        }
+        // Текущий буфер и еще по разу каждый (включая текущий с новым запасом)
+        for _ in 0..=self.buffers.len() {
+            if self.credit > 0 {
+                if let Some(value) = self.buffers[self.current].0.pop() {
+                    self.credit -= 1;
+                    return Some(value);
+                }
+                self.credit = 0; // Пустой — отдаем ход дальше
+            }
+            self.rotate();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(value: u8, count: usize) -> Arc<SafeRingBuffer> {
+        let buffer = SafeRingBuffer::new(count);
+        buffer.push_slice(&vec![value; count]).unwrap();
+        Arc::new(buffer)
+    }
+
+    #[test]
+    fn test_weights_are_respected() {
+        let mut scheduler = WeightedScheduler::new(vec![(filled(1, 30), 3), (filled(2, 30), 1)]);
+        let window: Vec<u8> = scheduler.by_ref().take(40).collect();
+        let from_first = window.iter().filter(|&&v| v == 1).count();
+        assert_eq!(from_first, 30); // 3:1 на окне из 40
+        assert_eq!(&window[..8], &[1, 1, 1, 2, 1, 1, 1, 2]);
+    }
+
+    #[test]
+    fn test_empty_buffers_are_skipped() {
+        let empty = Arc::new(SafeRingBuffer::new(4));
+        let mut scheduler = WeightedScheduler::new(vec![
+            (Arc::clone(&empty), 5),
+            (filled(7, 2), 1),
+            (filled(9, 1), 0), // Нулевой вес — никогда не опрашивается
+        ]);
+        assert_eq!(scheduler.next(), Some(7));
+        assert_eq!(scheduler.next(), Some(7));
+        assert_eq!(scheduler.next(), None);
+
+        empty.push(3).unwrap(); // Ожившие буферы снова опрашиваются
+        assert_eq!(scheduler.next(), Some(3));
+        assert_eq!(WeightedScheduler::new(Vec::new()).next(), None);
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use crate::{SafeRingBuffer, StrictMode};
+
+// Ограниченный счетный семафор поверх кольцевого буфера: каждое разрешение —
+// один байт-жетон в буфере.
+// Приближение: задумывался SafeRingBuffer<()> без полезной нагрузки, но
+// буфер не обобщен и хранит только u8, поэтому жетоном служит байт 0.
+// Semaphore::new(0) допустим: acquire ждет вечно, release возвращает false.
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: SafeRingBuffer,
+}
+
+impl Semaphore {
+    // Семафор с permits свободными разрешениями
+    pub fn new(permits: usize) -> Self {
+        // StrictMode::Error: release на нулевой вместимости — false, не паника
+        let buffer = SafeRingBuffer::new(permits).with_strict_mode(StrictMode::Error);
+        for _ in 0..permits {
+            buffer.push(0).expect("вместимость равна числу разрешений");
+        }
+        Semaphore { permits: buffer }
+    }
+
+    // Захват разрешения; блокируется, пока свободных нет
+    pub fn acquire(&self) {
+        self.permits
+            .pop_blocking()
+            .expect("буфер разрешений не закрывается");
+    }
+
+    // Захват с ограничением ожидания: false — за timeout разрешение так и
+    // не освободилось
+    pub fn acquire_timeout(&self, timeout: Duration) -> bool {
+        self.permits.pop_timeout(timeout).is_ok()
+    }
+
+    // Возврат разрешения. Больше максимума не бывает: лишний release
+    // игнорируется и возвращает false.
+    pub fn release(&self) -> bool {
+        self.permits.push_best_effort(0)
+    }
+
+    // Число свободных разрешений
+    pub fn available(&self) -> usize {
+        self.permits.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_semaphore_limits_concurrency() {
+        let semaphore = Arc::new(Semaphore::new(3));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let (semaphore, active, peak) = (
+                    Arc::clone(&semaphore),
+                    Arc::clone(&active),
+                    Arc::clone(&peak),
+                );
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        semaphore.acquire();
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(1));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        assert!(semaphore.release());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(semaphore.available(), 3);
+    }
+
+    #[test]
+    fn test_semaphore_release_saturates() {
+        let semaphore = Semaphore::new(2);
+        assert!(!semaphore.release()); // Уже максимум
+        semaphore.acquire();
+        assert_eq!(semaphore.available(), 1);
+        assert!(semaphore.release());
+        assert!(!semaphore.release());
+        assert_eq!(semaphore.available(), 2);
+    }
+
+    #[test]
+    fn test_semaphore_without_permits() {
+        let semaphore = Semaphore::new(0);
+        assert_eq!(semaphore.available(), 0);
+        assert!(!semaphore.release());
+        assert!(!semaphore.acquire_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_acquire_timeout() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        semaphore.acquire();
+
+        let start = std::time::Instant::now();
+        assert!(!semaphore.acquire_timeout(Duration::from_millis(20)));
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(20) && waited < Duration::from_secs(2));
+
+        // release во время ожидания: разрешение достается ждущему
+        let releaser = {
+            let semaphore = Arc::clone(&semaphore);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                semaphore.release()
+            })
+        };
+        assert!(semaphore.acquire_timeout(Duration::from_secs(5)));
+        assert!(releaser.join().unwrap());
+        assert_eq!(semaphore.available(), 0); // Разрешение израсходовано
+
+        // Duration::MAX — ожидание без срока, до release
+        let releaser = {
+            let semaphore = Arc::clone(&semaphore);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                semaphore.release()
+            })
+        };
+        assert!(semaphore.acquire_timeout(Duration::MAX));
+        assert!(releaser.join().unwrap());
+    }
+}
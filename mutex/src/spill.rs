@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use crate::SafeRingBuffer;
+
+// Буфер с выгрузкой переполнения в файл: пока память не заполнена, запись
+// идет в SafeRingBuffer; лишнее дописывается в конец файла и подчитывается
+// обратно, когда память опустеет. Порядок FIFO сохраняется на границе:
+// пока в файле что-то есть, новые значения тоже идут в файл.
+#[derive(Debug)]
+pub struct SpillRingBuffer {
+    memory: SafeRingBuffer,
+    capacity: usize, // Вместимость памяти
+    spill: Mutex<Spill>,
+}
+
+// Файл выгрузки: непрочитанные байты лежат в [read_pos, write_pos)
+#[derive(Debug)]
+struct Spill {
+    file: File,
+    read_pos: u64,
+    write_pos: u64,
+}
+
+impl Spill {
+    fn is_empty(&self) -> bool {
+        self.read_pos == self.write_pos
+    }
+}
+
+impl SpillRingBuffer {
+    // Файл должен быть открыт на чтение и запись; его содержимое
+    // затирается. Нулевая вместимость памяти — InvalidInput: подчитывать
+    // из файла было бы некуда.
+    pub fn new(capacity: usize, file: File) -> io::Result<Self> {
+        if capacity == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "spill buffer needs a non-zero memory capacity",
+            ));
+        }
+        file.set_len(0)?;
+        Ok(SpillRingBuffer {
+            memory: SafeRingBuffer::new(capacity),
+            capacity,
+            spill: Mutex::new(Spill {
+                file,
+                read_pos: 0,
+                write_pos: 0,
+            }),
+        })
+    }
+
+    pub fn push(&self, value: u8) -> io::Result<()> {
+        let mut spill = self.spill.lock().expect("мьютекс выгрузки отравлен");
+        if spill.is_empty() && self.memory.push(value).is_ok() {
+            return Ok(());
+        }
+        let write_pos = spill.write_pos;
+        spill.file.seek(SeekFrom::Start(write_pos))?;
+        spill.file.write_all(&[value])?;
+        spill.write_pos += 1;
+        Ok(())
+    }
+
+    // Сначала память; когда она пуста — подчитываем из файла
+    // сколько поместится
+    pub fn pop(&self) -> io::Result<Option<u8>> {
+        // В памяти всегда только значения старше выгруженных
+        if let Some(value) = self.memory.pop() {
+            return Ok(Some(value));
+        }
+        let mut spill = self.spill.lock().expect("мьютекс выгрузки отравлен");
+        if let Some(value) = self.memory.pop() {
+            return Ok(Some(value)); // Память успели подчитать, пока ждали
+        }
+        if spill.is_empty() {
+            return Ok(None);
+        }
+
+        let available = (spill.write_pos - spill.read_pos) as usize;
+        let mut chunk = vec![0; available.min(self.capacity)];
+        let read_pos = spill.read_pos;
+        spill.file.seek(SeekFrom::Start(read_pos))?;
+        spill.file.read_exact(&mut chunk)?;
+        spill.read_pos += chunk.len() as u64;
+        if spill.is_empty() {
+            // Все выгруженное прочитано — файл можно начать сначала
+            spill.file.set_len(0)?;
+            spill.read_pos = 0;
+            spill.write_pos = 0;
+        }
+
+        let (first, rest) = chunk.split_first().expect("в файле был хотя бы один байт");
+        self.memory
+            .push_slice(rest)
+            .expect("память пуста, фрагмент не больше вместимости");
+        Ok(Some(*first))
+    }
+
+    // Всего элементов: в памяти и в файле
+    pub fn len(&self) -> usize {
+        let spill = self.spill.lock().expect("мьютекс выгрузки отравлен");
+        self.memory.len() + (spill.write_pos - spill.read_pos) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Сколько байт сейчас лежит в файле выгрузки
+    pub fn spilled(&self) -> u64 {
+        let spill = self.spill.lock().expect("мьютекс выгрузки отравлен");
+        spill.write_pos - spill.read_pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    // Временный файл, удаляемый в конце теста
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> (Self, File) {
+            let path = std::env::temp_dir().join(format!("spill-{}-{name}", std::process::id()));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .unwrap();
+            (TempFile(path), file)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_overflow_goes_to_spill() {
+        let (_temp, file) = TempFile::new("overflow");
+        let buffer = SpillRingBuffer::new(4, file).unwrap();
+        for value in 0..10 {
+            buffer.push(value).unwrap();
+        }
+        assert_eq!(buffer.memory.len(), 4);
+        assert_eq!(buffer.spilled(), 6);
+        assert_eq!(buffer.len(), 10);
+    }
+
+    #[test]
+    fn test_zero_capacity_rejected() {
+        let (_temp, file) = TempFile::new("zero");
+        let error = SpillRingBuffer::new(0, file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_fifo_across_memory_and_spill() {
+        let (_temp, file) = TempFile::new("fifo");
+        let buffer = SpillRingBuffer::new(3, file).unwrap();
+        for value in 0..8 {
+            buffer.push(value).unwrap();
+        }
+        // Часть вычитываем, пока в файле еще есть данные, и дописываем новые
+        assert_eq!(buffer.pop().unwrap(), Some(0));
+        buffer.push(8).unwrap(); // Место в памяти есть, но файл не пуст
+        assert_eq!(buffer.spilled(), 6);
+
+        let mut drained = Vec::new();
+        while let Some(value) = buffer.pop().unwrap() {
+            drained.push(value);
+        }
+        assert_eq!(drained, (1..=8).collect::<Vec<u8>>());
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.spilled(), 0);
+    }
+}